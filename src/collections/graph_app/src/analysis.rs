@@ -0,0 +1,38 @@
+// Analyses that run directly over loaded tweets, with no graph database.
+use serde::Serialize;
+use std::io::Write;
+
+pub mod activity;
+pub mod metrics;
+pub mod network;
+pub mod rapid_fire;
+pub mod rings;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Csv,
+    Json,
+}
+
+// writes one row per record: a CSV table with a header, or a JSON array
+pub fn write_report<T: Serialize, W: Write>(
+    rows: &[T],
+    format: ReportFormat,
+    writer: W,
+) -> anyhow::Result<()> {
+    match format {
+        ReportFormat::Csv => {
+            let mut csv = csv::Writer::from_writer(writer);
+            for row in rows {
+                csv.serialize(row)?;
+            }
+            csv.flush()?;
+        }
+        ReportFormat::Json => {
+            let mut writer = writer;
+            serde_json::to_writer_pretty(&mut writer, rows)?;
+            writeln!(writer)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,242 @@
+// Tweet activity over time.
+//
+// Tweets are counted in fixed buckets (a minute, hour or day), for everyone
+// together and for each user, with the retweets and favorites those tweets
+// received. A bucket is a spike when its tweet count stands out against the
+// `window` buckets before it: above their mean by more than `threshold`
+// standard deviations. Empty buckets count toward the baseline as zeros, but
+// only buckets with tweets are reported.
+use crate::tweet::Tweet;
+use chrono::{DateTime, TimeZone, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Granularity {
+    Minute,
+    Hour,
+    Day,
+}
+
+impl Granularity {
+    fn seconds(self) -> i64 {
+        match self {
+            Granularity::Minute => 60,
+            Granularity::Hour => 60 * 60,
+            Granularity::Day => 24 * 60 * 60,
+        }
+    }
+
+    // buckets are numbered from the epoch
+    fn bucket(self, at: DateTime<Utc>) -> i64 {
+        at.timestamp().div_euclid(self.seconds())
+    }
+
+    fn start(self, bucket: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(bucket * self.seconds(), 0)
+            .single()
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ActivityConfig {
+    pub granularity: Granularity,
+    /// Buckets before the current one that make up its baseline.
+    pub window: usize,
+    /// Standard deviations above the baseline mean that make a spike.
+    pub threshold: f64,
+    /// Fewest tweets a spike can have, so one tweet after a quiet spell is not one.
+    pub min_tweets: u64,
+}
+
+impl Default for ActivityConfig {
+    fn default() -> Self {
+        ActivityConfig {
+            granularity: Granularity::Hour,
+            window: 24,
+            threshold: 3.0,
+            min_tweets: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ActivityBucket {
+    /// `None` for the bucket covering all users.
+    pub user: Option<String>,
+    pub start: DateTime<Utc>,
+    pub tweets: u64,
+    /// Retweets the bucket's tweets received.
+    pub retweets: u64,
+    /// Favorites the bucket's tweets received.
+    pub favorites: u64,
+    /// Mean tweets per bucket over the window before this one.
+    pub baseline: f64,
+    pub spike: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Totals {
+    tweets: u64,
+    retweets: u64,
+    favorites: u64,
+}
+
+impl Totals {
+    fn add(&mut self, tweet: &Tweet) {
+        self.tweets += 1;
+        self.retweets += u64::from(tweet.retweet_count);
+        self.favorites += u64::from(tweet.favorite_count);
+    }
+}
+
+/// Every non-empty bucket: the all-users series first, then each user's in
+/// name order, each in time order.
+pub fn activity(tweets: &[Tweet], config: &ActivityConfig) -> Vec<ActivityBucket> {
+    let mut global: BTreeMap<i64, Totals> = BTreeMap::new();
+    let mut by_user: BTreeMap<&str, BTreeMap<i64, Totals>> = BTreeMap::new();
+    for tweet in tweets {
+        let bucket = config.granularity.bucket(tweet.created_at);
+        global.entry(bucket).or_default().add(tweet);
+        by_user
+            .entry(&tweet.user)
+            .or_default()
+            .entry(bucket)
+            .or_default()
+            .add(tweet);
+    }
+
+    let mut rows = series(None, &global, config);
+    for (user, buckets) in by_user {
+        rows.extend(series(Some(user), &buckets, config));
+    }
+    rows
+}
+
+fn series(
+    user: Option<&str>,
+    buckets: &BTreeMap<i64, Totals>,
+    config: &ActivityConfig,
+) -> Vec<ActivityBucket> {
+    let buckets: Vec<(i64, Totals)> = buckets.iter().map(|(&b, &t)| (b, t)).collect();
+    let Some(&(first, _)) = buckets.first() else {
+        return Vec::new();
+    };
+    let window = config.window as i64;
+
+    let mut rows = Vec::with_capacity(buckets.len());
+    // running sums over the non-empty buckets inside the window
+    let (mut sum, mut sum_sq) = (0u64, 0u64);
+    let mut oldest = 0;
+    for &(bucket, totals) in &buckets {
+        while buckets[oldest].0 < bucket - window {
+            let count = buckets[oldest].1.tweets;
+            sum -= count;
+            sum_sq -= count * count;
+            oldest += 1;
+        }
+        // a series has no history before its first bucket
+        let slots = window.min(bucket - first);
+        let (baseline, spike) = if slots > 0 {
+            let mean = sum as f64 / slots as f64;
+            let std_dev = (sum_sq as f64 / slots as f64 - mean * mean).max(0.0).sqrt();
+            let count = totals.tweets as f64;
+            let spike =
+                totals.tweets >= config.min_tweets && count > mean + config.threshold * std_dev;
+            (mean, spike)
+        } else {
+            (0.0, false)
+        };
+        rows.push(ActivityBucket {
+            user: user.map(str::to_string),
+            start: config.granularity.start(bucket),
+            tweets: totals.tweets,
+            retweets: totals.retweets,
+            favorites: totals.favorites,
+            baseline,
+            spike,
+        });
+
+        sum += totals.tweets;
+        sum_sq += totals.tweets * totals.tweets;
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tweet(user: &str, minute: i64, favorites: u32) -> Tweet {
+        Tweet {
+            user: user.to_string(),
+            created_at: Utc.timestamp_opt(minute * 60, 0).unwrap(),
+            favorite_count: favorites,
+            ..Tweet::default()
+        }
+    }
+
+    fn config(window: usize) -> ActivityConfig {
+        ActivityConfig {
+            granularity: Granularity::Minute,
+            window,
+            threshold: 2.0,
+            min_tweets: 3,
+        }
+    }
+
+    #[test]
+    fn test_buckets_total_per_user_and_overall() {
+        let tweets = [tweet("a", 0, 1), tweet("b", 0, 2), tweet("a", 1, 4)];
+        let rows = activity(&tweets, &config(5));
+
+        let summary: Vec<(Option<&str>, i64, u64, u64)> = rows
+            .iter()
+            .map(|r| {
+                (
+                    r.user.as_deref(),
+                    r.start.timestamp(),
+                    r.tweets,
+                    r.favorites,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (None, 0, 2, 3),
+                (None, 60, 1, 4),
+                (Some("a"), 0, 1, 1),
+                (Some("a"), 60, 1, 4),
+                (Some("b"), 0, 1, 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_spike_against_rolling_baseline() {
+        // one tweet a minute for ten minutes, then five at once
+        let mut tweets: Vec<Tweet> = (0..10).map(|m| tweet("a", m, 0)).collect();
+        tweets.extend((0..5).map(|_| tweet("a", 10, 0)));
+        let rows = activity(&tweets, &config(5));
+        let users: Vec<&ActivityBucket> = rows.iter().filter(|r| r.user.is_some()).collect();
+
+        assert!(users[..10].iter().all(|r| !r.spike));
+        assert!(users[10].spike);
+        assert_eq!(users[10].baseline, 1.0);
+    }
+
+    #[test]
+    fn test_quiet_buckets_count_as_zero() {
+        // three tweets, then four after a long gap: the window saw only zeros
+        let mut tweets: Vec<Tweet> = (0..3).map(|_| tweet("a", 0, 0)).collect();
+        tweets.extend((0..4).map(|_| tweet("a", 100, 0)));
+        let rows = activity(&tweets, &config(5));
+
+        assert_eq!(rows[1].baseline, 0.0);
+        assert!(rows[1].spike);
+        // the very first bucket has no history to compare against
+        assert!(!rows[0].spike);
+    }
+}
@@ -0,0 +1,371 @@
+// Per-user centrality and community metrics over the retweet graph.
+//
+// Edges point from the retweeter to the retweeted account, so PageRank favours
+// accounts that get amplified. PageRank weights an edge by how many times it
+// was retweeted; betweenness counts shortest paths over distinct edges; label
+// propagation treats the graph as undirected and weighted. Everything runs in a
+// fixed order so the same tweets always give the same table.
+use super::network::RetweetNetwork;
+use crate::tweet::Tweet;
+use anyhow::Context;
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray, UInt64Array};
+use arrow_schema::{DataType, Field, Schema};
+use parquet::arrow::ArrowWriter;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::Write;
+use std::sync::Arc;
+
+#[derive(Debug, Clone)]
+pub struct MetricsConfig {
+    pub damping: f64,
+    /// PageRank stops once the scores move by less than this in total.
+    pub tolerance: f64,
+    pub max_iterations: usize,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            damping: 0.85,
+            tolerance: 1e-9,
+            max_iterations: 100,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UserMetrics {
+    pub user: String,
+    /// Retweets received.
+    pub in_degree: u64,
+    /// Retweets made.
+    pub out_degree: u64,
+    pub pagerank: f64,
+    pub betweenness: f64,
+    /// Communities are numbered from 0, largest first.
+    pub community: u64,
+    pub community_size: u64,
+}
+
+/// One row per user, highest PageRank first.
+pub fn user_metrics(tweets: &[Tweet], config: &MetricsConfig) -> Vec<UserMetrics> {
+    let network = RetweetNetwork::from_tweets(tweets);
+    let n = network.len();
+    let pagerank = pagerank(&network, config);
+    let betweenness = betweenness(&network);
+    let communities = label_propagation(&network, config.max_iterations);
+
+    let mut in_degree = vec![0; n];
+    for id in 0..n {
+        for &to in network.retweets(id) {
+            in_degree[to] += 1;
+        }
+    }
+    let mut sizes: HashMap<usize, u64> = HashMap::new();
+    for &label in &communities {
+        *sizes.entry(label).or_default() += 1;
+    }
+    // renumber communities by size, ties by their first member
+    let mut labels: Vec<usize> = sizes.keys().copied().collect();
+    labels.sort_by_key(|label| (std::cmp::Reverse(sizes[label]), *label));
+    let number: HashMap<usize, u64> = labels
+        .iter()
+        .enumerate()
+        .map(|(i, &label)| (label, i as u64))
+        .collect();
+
+    let mut rows: Vec<UserMetrics> = (0..n)
+        .map(|id| UserMetrics {
+            user: network.user(id).to_string(),
+            in_degree: in_degree[id],
+            out_degree: network.out_degree(id) as u64,
+            pagerank: pagerank[id],
+            betweenness: betweenness[id],
+            community: number[&communities[id]],
+            community_size: sizes[&communities[id]],
+        })
+        .collect();
+    rows.sort_by(|a, b| {
+        b.pagerank
+            .total_cmp(&a.pagerank)
+            .then_with(|| a.user.cmp(&b.user))
+    });
+    rows
+}
+
+// Power iteration. Users who retweeted no one spread their rank evenly over
+// everyone, so the scores always sum to 1.
+fn pagerank(network: &RetweetNetwork, config: &MetricsConfig) -> Vec<f64> {
+    let n = network.len();
+    if n == 0 {
+        return Vec::new();
+    }
+    let base = (1.0 - config.damping) / n as f64;
+    let mut rank = vec![1.0 / n as f64; n];
+    for _ in 0..config.max_iterations {
+        let dangling: f64 = (0..n)
+            .filter(|&id| network.out_degree(id) == 0)
+            .map(|id| rank[id])
+            .sum();
+        let mut next = vec![base + config.damping * dangling / n as f64; n];
+        for (id, &score) in rank.iter().enumerate() {
+            let retweets = network.retweets(id);
+            let share = config.damping * score / retweets.len().max(1) as f64;
+            for &to in retweets {
+                next[to] += share;
+            }
+        }
+        let change: f64 = rank.iter().zip(&next).map(|(a, b)| (a - b).abs()).sum();
+        rank = next;
+        if change < config.tolerance {
+            break;
+        }
+    }
+    rank
+}
+
+// Brandes' algorithm on the directed graph with repeat retweets collapsed.
+// O(users * edges), which is fine for the exports we get.
+fn betweenness(network: &RetweetNetwork) -> Vec<f64> {
+    let n = network.len();
+    let adjacency: Vec<Vec<usize>> = (0..n)
+        .map(|id| {
+            let mut targets = network.retweets(id).to_vec();
+            targets.sort_unstable();
+            targets.dedup();
+            targets.retain(|&to| to != id);
+            targets
+        })
+        .collect();
+
+    let mut centrality = vec![0.0; n];
+    let mut order = Vec::with_capacity(n);
+    let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); n];
+    let mut paths = vec![0.0; n];
+    let mut distance = vec![usize::MAX; n];
+    let mut dependency = vec![0.0; n];
+    let mut queue = VecDeque::new();
+
+    for source in 0..n {
+        for id in 0..n {
+            predecessors[id].clear();
+            paths[id] = 0.0;
+            distance[id] = usize::MAX;
+            dependency[id] = 0.0;
+        }
+        order.clear();
+        paths[source] = 1.0;
+        distance[source] = 0;
+        queue.push_back(source);
+
+        while let Some(node) = queue.pop_front() {
+            order.push(node);
+            for &next in &adjacency[node] {
+                if distance[next] == usize::MAX {
+                    distance[next] = distance[node] + 1;
+                    queue.push_back(next);
+                }
+                if distance[next] == distance[node] + 1 {
+                    paths[next] += paths[node];
+                    predecessors[next].push(node);
+                }
+            }
+        }
+
+        for &node in order.iter().rev() {
+            for &prev in &predecessors[node] {
+                dependency[prev] += paths[prev] / paths[node] * (1.0 + dependency[node]);
+            }
+            if node != source {
+                centrality[node] += dependency[node];
+            }
+        }
+    }
+    centrality
+}
+
+// Each user repeatedly adopts the label carrying the most retweet weight among
+// their neighbours, ties going to the smallest label. Users are visited in id
+// order rather than shuffled so runs are reproducible. `max_iterations` also
+// caps this loop.
+fn label_propagation(network: &RetweetNetwork, max_iterations: usize) -> Vec<usize> {
+    let n = network.len();
+    let mut neighbours: Vec<BTreeMap<usize, f64>> = vec![BTreeMap::new(); n];
+    for from in 0..n {
+        for &to in network.retweets(from) {
+            if from != to {
+                *neighbours[from].entry(to).or_default() += 1.0;
+                *neighbours[to].entry(from).or_default() += 1.0;
+            }
+        }
+    }
+
+    let mut labels: Vec<usize> = (0..n).collect();
+    for _ in 0..max_iterations {
+        let mut changed = false;
+        for id in 0..n {
+            let mut weights: BTreeMap<usize, f64> = BTreeMap::new();
+            for (&other, &weight) in &neighbours[id] {
+                *weights.entry(labels[other]).or_default() += weight;
+            }
+            // labels come in ascending order, so only a strictly heavier label
+            // replaces one already seen
+            let mut best: Option<(usize, f64)> = None;
+            for (&label, &weight) in &weights {
+                if best.is_none_or(|(_, top)| weight > top) {
+                    best = Some((label, weight));
+                }
+            }
+            // a label tied for heaviest is kept, or neighbours could swap forever
+            let current = weights.get(&labels[id]).copied().unwrap_or(0.0);
+            if let Some((label, top)) = best
+                && top > current
+            {
+                labels[id] = label;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    labels
+}
+
+pub fn write_parquet<W: Write + Send>(rows: &[UserMetrics], writer: W) -> anyhow::Result<()> {
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("user", DataType::Utf8, false),
+        Field::new("in_degree", DataType::UInt64, false),
+        Field::new("out_degree", DataType::UInt64, false),
+        Field::new("pagerank", DataType::Float64, false),
+        Field::new("betweenness", DataType::Float64, false),
+        Field::new("community", DataType::UInt64, false),
+        Field::new("community_size", DataType::UInt64, false),
+    ]));
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(rows.iter().map(|r| &r.user))),
+        Arc::new(UInt64Array::from_iter_values(
+            rows.iter().map(|r| r.in_degree),
+        )),
+        Arc::new(UInt64Array::from_iter_values(
+            rows.iter().map(|r| r.out_degree),
+        )),
+        Arc::new(Float64Array::from_iter_values(
+            rows.iter().map(|r| r.pagerank),
+        )),
+        Arc::new(Float64Array::from_iter_values(
+            rows.iter().map(|r| r.betweenness),
+        )),
+        Arc::new(UInt64Array::from_iter_values(
+            rows.iter().map(|r| r.community),
+        )),
+        Arc::new(UInt64Array::from_iter_values(
+            rows.iter().map(|r| r.community_size),
+        )),
+    ];
+    let batch = RecordBatch::try_new(schema.clone(), columns).context("building metrics batch")?;
+    let mut parquet = ArrowWriter::try_new(writer, schema, None)?;
+    parquet.write(&batch)?;
+    parquet.close()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn retweet(user: &str, from: &str) -> Tweet {
+        Tweet {
+            user: user.to_string(),
+            retweet_from_user: Some(from.to_string()),
+            ..Tweet::default()
+        }
+    }
+
+    fn metrics(tweets: &[Tweet]) -> HashMap<String, UserMetrics> {
+        user_metrics(tweets, &MetricsConfig::default())
+            .into_iter()
+            .map(|row| (row.user.clone(), row))
+            .collect()
+    }
+
+    #[test]
+    fn test_pagerank_sums_to_one_and_favours_the_amplified() {
+        let tweets = [
+            retweet("a", "hub"),
+            retweet("b", "hub"),
+            retweet("c", "hub"),
+        ];
+        let rows = user_metrics(&tweets, &MetricsConfig::default());
+
+        assert_eq!(rows[0].user, "hub");
+        assert_eq!(rows[0].in_degree, 3);
+        let total: f64 = rows.iter().map(|r| r.pagerank).sum();
+        assert!((total - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_betweenness_counts_paths_through_a_user() {
+        // a -> b -> c: b lies on the only path from a to c
+        let rows = metrics(&[retweet("a", "b"), retweet("b", "c")]);
+        assert_eq!(rows["b"].betweenness, 1.0);
+        assert_eq!(rows["a"].betweenness, 0.0);
+        assert_eq!(rows["c"].betweenness, 0.0);
+    }
+
+    #[test]
+    fn test_handles_in_any_case_are_one_user() {
+        // alice -> Bob -> Carol, with the retweets naming accounts as written
+        let data = "1,alice,1471272620000,,,,,RT @Bob: hi,100,,,,,,,\n\
+                    2,BOB,1471272680000,,,,,RT @CAROL: hello,101,,,,,,,\n";
+        let (tweets, _) = crate::loader::read_tweets(data.as_bytes()).unwrap();
+        let rows = metrics(&tweets);
+
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows["bob"].in_degree, 1);
+        assert_eq!(rows["bob"].out_degree, 1);
+        assert_eq!(rows["bob"].betweenness, 1.0);
+        assert_eq!(rows["bob"].community, rows["carol"].community);
+    }
+
+    #[test]
+    fn test_label_propagation_separates_cliques() {
+        let mut tweets = Vec::new();
+        for (x, y) in [
+            ("a", "b"),
+            ("b", "c"),
+            ("c", "a"),
+            ("x", "y"),
+            ("y", "z"),
+            ("z", "x"),
+        ] {
+            tweets.push(retweet(x, y));
+            tweets.push(retweet(y, x));
+        }
+        // a single weak link between the two groups
+        tweets.push(retweet("c", "x"));
+        let rows = metrics(&tweets);
+
+        assert_eq!(rows["a"].community, rows["b"].community);
+        assert_eq!(rows["a"].community, rows["c"].community);
+        assert_eq!(rows["x"].community, rows["z"].community);
+        assert_ne!(rows["a"].community, rows["x"].community);
+        assert_eq!(rows["a"].community_size, 3);
+    }
+
+    #[test]
+    fn test_parquet_output_reads_back() {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+
+        let rows = user_metrics(&[retweet("a", "b")], &MetricsConfig::default());
+        let path = std::env::temp_dir().join(format!("metrics-{}.parquet", std::process::id()));
+        write_parquet(&rows, std::fs::File::create(&path).unwrap()).unwrap();
+
+        let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let metadata = reader.metadata().file_metadata();
+        assert_eq!(metadata.num_rows(), 2);
+        assert_eq!(metadata.schema_descr().num_columns(), 7);
+        std::fs::remove_file(path).unwrap();
+    }
+}
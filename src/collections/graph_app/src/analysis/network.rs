@@ -0,0 +1,133 @@
+// The user -> retweeted-user multigraph.
+//
+// Users are numbered in the order they are first seen and edges are kept per
+// retweet, so a user who retweets the same account twice has two parallel edges.
+use crate::tweet::Tweet;
+use std::collections::HashMap;
+
+#[derive(Debug, Default)]
+pub struct RetweetNetwork {
+    users: Vec<String>,
+    index: HashMap<String, usize>,
+    // out[u] holds one entry per retweet u made
+    out: Vec<Vec<usize>>,
+}
+
+impl RetweetNetwork {
+    pub fn from_tweets(tweets: &[Tweet]) -> Self {
+        let mut network = RetweetNetwork::default();
+        for tweet in tweets {
+            let from = network.intern(&tweet.user);
+            if let Some(retweeted) = &tweet.retweet_from_user {
+                let to = network.intern(retweeted);
+                network.out[from].push(to);
+            }
+        }
+        network
+    }
+
+    fn intern(&mut self, user: &str) -> usize {
+        if let Some(&id) = self.index.get(user) {
+            return id;
+        }
+        let id = self.users.len();
+        self.users.push(user.to_string());
+        self.index.insert(user.to_string(), id);
+        self.out.push(Vec::new());
+        id
+    }
+
+    pub fn len(&self) -> usize {
+        self.users.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+
+    pub fn user(&self, id: usize) -> &str {
+        &self.users[id]
+    }
+
+    pub fn id(&self, user: &str) -> Option<usize> {
+        self.index.get(user).copied()
+    }
+
+    /// Every retweet `id` made, one entry per retweet.
+    pub fn retweets(&self, id: usize) -> &[usize] {
+        &self.out[id]
+    }
+
+    /// Total retweets made by `id`, counting repeats.
+    pub fn out_degree(&self, id: usize) -> usize {
+        self.out[id].len()
+    }
+
+    /// Number of different accounts `id` retweeted.
+    pub fn distinct_out_degree(&self, id: usize) -> usize {
+        let mut targets = self.out[id].clone();
+        targets.sort_unstable();
+        targets.dedup();
+        targets.len()
+    }
+
+    /// Strongly connected components, found with an iterative Tarjan's
+    /// algorithm so long retweet chains cannot overflow the stack.
+    pub fn strongly_connected_components(&self) -> Vec<Vec<usize>> {
+        const UNVISITED: usize = usize::MAX;
+        let n = self.users.len();
+        let mut index = vec![UNVISITED; n];
+        let mut low = vec![0; n];
+        let mut on_stack = vec![false; n];
+        let mut stack = Vec::new();
+        let mut components = Vec::new();
+        let mut next_index = 0;
+
+        for root in 0..n {
+            if index[root] != UNVISITED {
+                continue;
+            }
+            // (node, position in its edge list)
+            let mut call_stack = vec![(root, 0)];
+            index[root] = next_index;
+            low[root] = next_index;
+            next_index += 1;
+            stack.push(root);
+            on_stack[root] = true;
+
+            while let Some(&mut (node, ref mut edge)) = call_stack.last_mut() {
+                if let Some(&next) = self.out[node].get(*edge) {
+                    *edge += 1;
+                    if index[next] == UNVISITED {
+                        index[next] = next_index;
+                        low[next] = next_index;
+                        next_index += 1;
+                        stack.push(next);
+                        on_stack[next] = true;
+                        call_stack.push((next, 0));
+                    } else if on_stack[next] {
+                        low[node] = low[node].min(index[next]);
+                    }
+                    continue;
+                }
+
+                call_stack.pop();
+                if let Some(&(parent, _)) = call_stack.last() {
+                    low[parent] = low[parent].min(low[node]);
+                }
+                if low[node] == index[node] {
+                    let mut component = Vec::new();
+                    while let Some(member) = stack.pop() {
+                        on_stack[member] = false;
+                        component.push(member);
+                        if member == node {
+                            break;
+                        }
+                    }
+                    components.push(component);
+                }
+            }
+        }
+        components
+    }
+}
@@ -0,0 +1,203 @@
+// Rapid-fire posting detection.
+//
+// For each user, tweets are put in time order and two things are measured:
+// how many consecutive tweets came less than `max_gap_secs` apart, and how many
+// bursts of `burst_size` tweets fit inside `burst_window_secs`. Bursts are
+// counted with a sliding window, so overlapping bursts each count.
+use crate::tweet::Tweet;
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+pub struct RapidFireConfig {
+    /// Consecutive tweets closer together than this are rapid posts.
+    pub max_gap_secs: i64,
+    /// Tweets needed in one window to count as a burst.
+    pub burst_size: usize,
+    /// Width of the burst window.
+    pub burst_window_secs: i64,
+    /// Keep only the top users; `None` keeps everyone with any activity.
+    pub limit: Option<usize>,
+}
+
+impl Default for RapidFireConfig {
+    fn default() -> Self {
+        RapidFireConfig {
+            max_gap_secs: 30,
+            burst_size: 5,
+            burst_window_secs: 300,
+            limit: Some(10),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RapidFireUser {
+    pub rank: usize,
+    pub user: String,
+    pub tweets: usize,
+    pub rapid_posts: usize,
+    pub bursts: usize,
+    /// Most tweets inside any single burst window.
+    pub max_in_window: usize,
+    pub shortest_gap_secs: Option<i64>,
+}
+
+/// Users with at least one rapid post or burst, ranked by rapid posts, then bursts.
+pub fn rapid_fire(tweets: &[Tweet], config: &RapidFireConfig) -> Vec<RapidFireUser> {
+    let mut times_by_user: HashMap<&str, Vec<i64>> = HashMap::new();
+    for tweet in tweets {
+        times_by_user
+            .entry(tweet.user.as_str())
+            .or_default()
+            .push(tweet.created_at.timestamp());
+    }
+
+    let mut users: Vec<RapidFireUser> = times_by_user
+        .into_iter()
+        .map(|(user, mut times)| {
+            times.sort_unstable();
+            score(user, &times, config)
+        })
+        .filter(|u| u.rapid_posts > 0 || u.bursts > 0)
+        .collect();
+
+    users.sort_by(|a, b| {
+        b.rapid_posts
+            .cmp(&a.rapid_posts)
+            .then_with(|| b.bursts.cmp(&a.bursts))
+            .then_with(|| a.user.cmp(&b.user))
+    });
+    if let Some(limit) = config.limit {
+        users.truncate(limit);
+    }
+    for (i, user) in users.iter_mut().enumerate() {
+        user.rank = i + 1;
+    }
+    users
+}
+
+// `times` must be sorted
+fn score(user: &str, times: &[i64], config: &RapidFireConfig) -> RapidFireUser {
+    let gaps = times.windows(2).map(|pair| pair[1] - pair[0]);
+    let rapid_posts = gaps
+        .clone()
+        .filter(|&gap| gap < config.max_gap_secs)
+        .count();
+    let shortest_gap_secs = gaps.min();
+
+    // two pointers: for each tweet, the tweets that follow it within the window
+    let mut bursts = 0;
+    let mut max_in_window = 0;
+    let mut end = 0;
+    for (start, &t) in times.iter().enumerate() {
+        while end < times.len() && times[end] - t <= config.burst_window_secs {
+            end += 1;
+        }
+        let in_window = end - start;
+        max_in_window = max_in_window.max(in_window);
+        if config.burst_size > 0 && in_window >= config.burst_size {
+            bursts += 1;
+        }
+    }
+
+    RapidFireUser {
+        rank: 0,
+        user: user.to_string(),
+        tweets: times.len(),
+        rapid_posts,
+        bursts,
+        max_in_window,
+        shortest_gap_secs,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::{ReportFormat, write_report};
+    use chrono::{TimeZone, Utc};
+
+    fn tweet(user: &str, secs: i64) -> Tweet {
+        Tweet {
+            user: user.to_string(),
+            created_at: Utc.timestamp_opt(secs, 0).unwrap(),
+            ..Tweet::default()
+        }
+    }
+
+    fn config(max_gap_secs: i64, burst_size: usize, burst_window_secs: i64) -> RapidFireConfig {
+        RapidFireConfig {
+            max_gap_secs,
+            burst_size,
+            burst_window_secs,
+            limit: None,
+        }
+    }
+
+    #[test]
+    fn test_gap_threshold_is_configurable() {
+        let tweets = vec![
+            tweet("a", 0),
+            tweet("a", 20),
+            tweet("a", 100),
+            tweet("b", 0),
+        ];
+
+        let strict = rapid_fire(&tweets, &config(10, 0, 0));
+        assert!(strict.is_empty());
+
+        let loose = rapid_fire(&tweets, &config(90, 0, 0));
+        assert_eq!(loose.len(), 1);
+        assert_eq!(loose[0].rapid_posts, 2);
+        assert_eq!(loose[0].shortest_gap_secs, Some(20));
+    }
+
+    #[test]
+    fn test_sliding_window_bursts() {
+        // 4 tweets within 60s, then a lone tweet much later
+        let tweets: Vec<Tweet> = [0, 10, 40, 60, 1000]
+            .iter()
+            .map(|&t| tweet("a", t))
+            .collect();
+
+        let users = rapid_fire(&tweets, &config(0, 3, 60));
+        assert_eq!(users[0].max_in_window, 4);
+        // windows starting at 0 and 10 hold at least 3 tweets
+        assert_eq!(users[0].bursts, 2);
+    }
+
+    #[test]
+    fn test_ranking_and_limit() {
+        let tweets = vec![
+            tweet("a", 0),
+            tweet("a", 5),
+            tweet("b", 0),
+            tweet("b", 5),
+            tweet("b", 10),
+            tweet("c", 0),
+            tweet("c", 5),
+        ];
+        let users = rapid_fire(
+            &tweets,
+            &RapidFireConfig {
+                limit: Some(2),
+                ..config(30, 0, 0)
+            },
+        );
+        let ranked: Vec<(usize, &str)> = users.iter().map(|u| (u.rank, u.user.as_str())).collect();
+        assert_eq!(ranked, vec![(1, "b"), (2, "a")]);
+    }
+
+    #[test]
+    fn test_csv_report() {
+        let users = rapid_fire(&[tweet("a", 0), tweet("a", 1)], &config(30, 0, 0));
+        let mut out = Vec::new();
+        write_report(&users, ReportFormat::Csv, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "rank,user,tweets,rapid_posts,bursts,max_in_window,shortest_gap_secs\n\
+             1,a,2,1,0,1,1\n"
+        );
+    }
+}
@@ -0,0 +1,182 @@
+// Retweet-ring detection.
+//
+// Two signals are reported. Heavy retweeters are users who retweeted more than
+// `min_retweeted` distinct accounts, which is what the old Cypher query looked
+// for. Rings are strongly connected components of the retweet graph: groups in
+// which every member is reachable from every other by following retweets, the
+// signature of accounts amplifying each other.
+use super::network::RetweetNetwork;
+use crate::tweet::Tweet;
+use std::collections::HashSet;
+
+#[derive(Debug, Clone)]
+pub struct RingConfig {
+    /// Report users who retweeted strictly more than this many distinct accounts.
+    pub min_retweeted: usize,
+    /// Smallest strongly connected component reported as a ring.
+    pub min_ring_size: usize,
+}
+
+impl Default for RingConfig {
+    fn default() -> Self {
+        RingConfig {
+            min_retweeted: 50,
+            min_ring_size: 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Retweeter {
+    pub user: String,
+    pub distinct_accounts: usize,
+    pub retweets: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ring {
+    /// Members in alphabetical order.
+    pub members: Vec<String>,
+    /// Retweets from one member of the ring to another.
+    pub internal_retweets: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct RingReport {
+    /// Most distinct accounts first.
+    pub heavy_retweeters: Vec<Retweeter>,
+    /// Largest ring first.
+    pub rings: Vec<Ring>,
+}
+
+pub fn detect_rings(tweets: &[Tweet], config: &RingConfig) -> RingReport {
+    let network = RetweetNetwork::from_tweets(tweets);
+
+    let mut heavy_retweeters: Vec<Retweeter> = (0..network.len())
+        .map(|id| (id, network.distinct_out_degree(id)))
+        .filter(|&(_, distinct_accounts)| distinct_accounts > config.min_retweeted)
+        .map(|(id, distinct_accounts)| Retweeter {
+            user: network.user(id).to_string(),
+            distinct_accounts,
+            retweets: network.out_degree(id),
+        })
+        .collect();
+    heavy_retweeters.sort_by(|a, b| {
+        b.distinct_accounts
+            .cmp(&a.distinct_accounts)
+            .then_with(|| a.user.cmp(&b.user))
+    });
+
+    let mut rings: Vec<Ring> = network
+        .strongly_connected_components()
+        .into_iter()
+        .filter(|component| component.len() >= config.min_ring_size.max(1))
+        .map(|component| {
+            let in_ring: HashSet<usize> = component.iter().copied().collect();
+            let internal_retweets = component
+                .iter()
+                .flat_map(|&id| network.retweets(id))
+                .filter(|to| in_ring.contains(to))
+                .count();
+            let mut members: Vec<String> = component
+                .iter()
+                .map(|&id| network.user(id).to_string())
+                .collect();
+            members.sort();
+            Ring {
+                members,
+                internal_retweets,
+            }
+        })
+        .collect();
+    rings.sort_by(|a, b| {
+        b.members
+            .len()
+            .cmp(&a.members.len())
+            .then_with(|| a.members.cmp(&b.members))
+    });
+
+    RingReport {
+        heavy_retweeters,
+        rings,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::read_tweets;
+
+    // an empty `from` makes an original tweet
+    fn retweet(user: &str, from: &str) -> Tweet {
+        Tweet {
+            user: user.to_string(),
+            retweet_from_user: Some(from.to_string()).filter(|u| !u.is_empty()),
+            ..Tweet::default()
+        }
+    }
+
+    #[test]
+    fn test_rings_are_strongly_connected_components() {
+        // a -> b -> c -> a is a ring, d only points into it, e retweets nobody
+        let tweets = vec![
+            retweet("a", "b"),
+            retweet("b", "c"),
+            retweet("c", "a"),
+            retweet("c", "a"),
+            retweet("d", "a"),
+            retweet("e", ""),
+        ];
+        let report = detect_rings(&tweets, &RingConfig::default());
+
+        assert_eq!(
+            report.rings,
+            vec![Ring {
+                members: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+                internal_retweets: 4,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_ring_found_across_handle_case() {
+        // retweets name accounts as written, authors are stored in lowercase
+        let data = "1,ten_gop,1471272620000,,,,,RT @RightNPR: hi,100,,,,,,,\n\
+                    2,rightnpr,1471272680000,,,,,RT @TEN_GOP: hello,101,,,,,,,\n";
+        let (tweets, _) = read_tweets(data.as_bytes()).unwrap();
+        let report = detect_rings(&tweets, &RingConfig::default());
+
+        assert_eq!(
+            report.rings,
+            vec![Ring {
+                members: vec!["rightnpr".to_string(), "ten_gop".to_string()],
+                internal_retweets: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_heavy_retweeter_threshold_counts_distinct_accounts() {
+        let tweets = vec![
+            retweet("a", "x"),
+            retweet("a", "x"),
+            retweet("a", "y"),
+            retweet("b", "x"),
+        ];
+        let config = RingConfig {
+            min_retweeted: 1,
+            ..RingConfig::default()
+        };
+        let report = detect_rings(&tweets, &config);
+
+        assert_eq!(
+            report.heavy_retweeters,
+            vec![Retweeter {
+                user: "a".to_string(),
+                distinct_accounts: 2,
+                retweets: 3,
+            }]
+        );
+        assert!(report.rings.is_empty());
+    }
+}
@@ -0,0 +1,112 @@
+// Neo4j connection settings.
+//
+// Settings are layered: command-line flags and environment variables win,
+// then the config file, then the defaults below. The config file is TOML:
+//
+//     [neo4j]
+//     uri = "neo4j+s://example.databases.neo4j.io"
+//     user = "neo4j"
+//     password = "..."
+use anyhow::{Context, bail};
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const DEFAULT_URI: &str = "neo4j+s://d7de04cc.databases.neo4j.io";
+pub const DEFAULT_USER: &str = "neo4j";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ConnectionSettings {
+    pub uri: Option<String>,
+    pub user: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    neo4j: ConnectionSettings,
+}
+
+impl ConnectionSettings {
+    /// Reads the `[neo4j]` table of a config file. A missing file is only an
+    /// error when `required` is set, i.e. the user named it explicitly.
+    pub fn from_file(path: &Path, required: bool) -> anyhow::Result<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => {
+                return Ok(ConnectionSettings::default());
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("reading config {}", path.display()));
+            }
+        };
+        let config: ConfigFile = toml::from_str(&contents)
+            .with_context(|| format!("parsing config {}", path.display()))?;
+        Ok(config.neo4j)
+    }
+
+    /// Fills anything unset here from `fallback`.
+    pub fn or(self, fallback: ConnectionSettings) -> Self {
+        ConnectionSettings {
+            uri: self.uri.or(fallback.uri),
+            user: self.user.or(fallback.user),
+            password: self.password.or(fallback.password),
+        }
+    }
+
+    pub fn resolve(self) -> anyhow::Result<Connection> {
+        let Some(password) = self.password else {
+            bail!(
+                "no Neo4j password: pass --password, set NEO4J_PASSWORD, \
+                 or add `password` to the [neo4j] table of the config file"
+            );
+        };
+        Ok(Connection {
+            uri: self.uri.unwrap_or_else(|| DEFAULT_URI.to_string()),
+            user: self.user.unwrap_or_else(|| DEFAULT_USER.to_string()),
+            password,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Connection {
+    pub uri: String,
+    pub user: String,
+    pub password: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flags_override_file_and_defaults_fill_gaps() {
+        let file: ConfigFile =
+            toml::from_str("[neo4j]\nuri = \"bolt://file:7687\"\npassword = \"from-file\"\n")
+                .unwrap();
+        let flags = ConnectionSettings {
+            password: Some("from-flag".to_string()),
+            ..ConnectionSettings::default()
+        };
+
+        let connection = flags.or(file.neo4j).resolve().unwrap();
+        assert_eq!(connection.uri, "bolt://file:7687");
+        assert_eq!(connection.user, DEFAULT_USER);
+        assert_eq!(connection.password, "from-flag");
+    }
+
+    #[test]
+    fn test_missing_password_is_an_error() {
+        assert!(ConnectionSettings::default().resolve().is_err());
+    }
+
+    #[test]
+    fn test_missing_default_config_file_is_fine() {
+        let path = Path::new("does-not-exist.toml");
+        assert!(ConnectionSettings::from_file(path, false).is_ok());
+        assert!(ConnectionSettings::from_file(path, true).is_err());
+    }
+}
@@ -0,0 +1,427 @@
+// Writes the tweet graph to files other tools can open.
+//
+// The exported graph has a node per user and per tweet, a `TWEETED` edge from
+// each author to their tweets and a `RETWEETED` edge from a user to each account
+// they retweeted, weighted by how many times they did. GraphML and GEXF carry
+// node attributes and load straight into Gephi; the edge list is a CSV with
+// Gephi's `Source,Target,Label,Weight` headers for tools that only want edges.
+use crate::tweet::{Tweet, latest_by_id};
+use anyhow::bail;
+use std::collections::BTreeMap;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    GraphMl,
+    Gexf,
+    EdgeList,
+}
+
+impl ExportFormat {
+    pub fn from_path(path: &Path) -> anyhow::Result<Self> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("graphml") => Ok(ExportFormat::GraphMl),
+            Some("gexf") => Ok(ExportFormat::Gexf),
+            Some("csv") => Ok(ExportFormat::EdgeList),
+            _ => bail!(
+                "cannot tell the export format of {}; use .graphml, .gexf or .csv",
+                path.display()
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    User,
+    Tweet,
+}
+
+impl NodeKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            NodeKind::User => "user",
+            NodeKind::Tweet => "tweet",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Node {
+    pub id: String,
+    pub kind: NodeKind,
+    pub label: String,
+    /// Tweets posted: the user's count, or 1 for a tweet.
+    pub tweets: u64,
+    /// Favorites received, summed over a user's tweets.
+    pub favorites: u64,
+    /// Times retweeted, summed over a user's tweets.
+    pub retweets: u64,
+    /// Tweets only.
+    pub created_at: Option<String>,
+    /// Tweets only.
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    Tweeted,
+    Retweeted,
+}
+
+impl EdgeKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            EdgeKind::Tweeted => "TWEETED",
+            EdgeKind::Retweeted => "RETWEETED",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edge {
+    pub source: String,
+    pub target: String,
+    pub kind: EdgeKind,
+    pub weight: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct ExportGraph {
+    pub nodes: Vec<Node>,
+    pub edges: Vec<Edge>,
+}
+
+fn user_node(user: &str) -> Node {
+    Node {
+        id: format!("u:{}", user),
+        kind: NodeKind::User,
+        label: user.to_string(),
+        tweets: 0,
+        favorites: 0,
+        retweets: 0,
+        created_at: None,
+        text: None,
+    }
+}
+
+impl ExportGraph {
+    /// Builds the graph from loaded tweets. A tweet id seen more than once is
+    /// exported once, as last seen, as loading it would leave it. `with_tweets`
+    /// false leaves out tweet nodes and their edges, keeping just the retweet
+    /// network between users.
+    pub fn from_tweets(tweets: &[Tweet], with_tweets: bool) -> Self {
+        let mut users: BTreeMap<&str, Node> = BTreeMap::new();
+        let mut retweets: BTreeMap<(&str, &str), u64> = BTreeMap::new();
+        let mut tweet_nodes = Vec::new();
+        let mut tweeted = Vec::new();
+
+        for tweet in latest_by_id(tweets) {
+            let author = users
+                .entry(&tweet.user)
+                .or_insert_with(|| user_node(&tweet.user));
+            author.tweets += 1;
+            author.favorites += u64::from(tweet.favorite_count);
+            author.retweets += u64::from(tweet.retweet_count);
+            if let Some(source) = &tweet.retweet_from_user {
+                users.entry(source).or_insert_with(|| user_node(source));
+                *retweets.entry((&tweet.user, source)).or_default() += 1;
+            }
+            if with_tweets {
+                let id = format!("t:{}", tweet.id);
+                tweeted.push(Edge {
+                    source: format!("u:{}", tweet.user),
+                    target: id.clone(),
+                    kind: EdgeKind::Tweeted,
+                    weight: 1,
+                });
+                tweet_nodes.push(Node {
+                    id,
+                    kind: NodeKind::Tweet,
+                    label: tweet.id.clone(),
+                    tweets: 1,
+                    favorites: u64::from(tweet.favorite_count),
+                    retweets: u64::from(tweet.retweet_count),
+                    created_at: Some(tweet.created_at.to_rfc3339()),
+                    text: Some(tweet.text.clone()),
+                });
+            }
+        }
+
+        let retweeted = retweets.into_iter().map(|((from, to), n)| Edge {
+            source: format!("u:{}", from),
+            target: format!("u:{}", to),
+            kind: EdgeKind::Retweeted,
+            weight: n,
+        });
+        ExportGraph {
+            nodes: users.into_values().chain(tweet_nodes).collect(),
+            edges: retweeted.chain(tweeted).collect(),
+        }
+    }
+
+    pub fn write<W: Write>(&self, format: ExportFormat, writer: W) -> anyhow::Result<()> {
+        match format {
+            ExportFormat::GraphMl => self.write_graphml(writer)?,
+            ExportFormat::Gexf => self.write_gexf(writer)?,
+            ExportFormat::EdgeList => self.write_edge_list(writer)?,
+        }
+        Ok(())
+    }
+
+    fn write_graphml<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut w = BufWriter::new(writer);
+        writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            w,
+            r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+        )?;
+        let keys = [
+            ("kind", "node", "string"),
+            ("label", "node", "string"),
+            ("tweets", "node", "long"),
+            ("favorites", "node", "long"),
+            ("retweets", "node", "long"),
+            ("created_at", "node", "string"),
+            ("text", "node", "string"),
+            ("kind", "edge", "string"),
+            ("weight", "edge", "long"),
+        ];
+        for (name, target, kind) in keys {
+            writeln!(
+                w,
+                r#"  <key id="{target}_{name}" for="{target}" attr.name="{name}" attr.type="{kind}"/>"#
+            )?;
+        }
+        writeln!(w, r#"  <graph id="tweets" edgedefault="directed">"#)?;
+        for node in &self.nodes {
+            writeln!(w, r#"    <node id="{}">"#, escape(&node.id))?;
+            let mut data = vec![
+                ("kind", node.kind.as_str().to_string()),
+                ("label", node.label.clone()),
+                ("tweets", node.tweets.to_string()),
+                ("favorites", node.favorites.to_string()),
+                ("retweets", node.retweets.to_string()),
+            ];
+            data.extend(node.created_at.clone().map(|at| ("created_at", at)));
+            data.extend(node.text.clone().map(|text| ("text", text)));
+            for (key, value) in data {
+                writeln!(
+                    w,
+                    r#"      <data key="node_{}">{}</data>"#,
+                    key,
+                    escape(&value)
+                )?;
+            }
+            writeln!(w, "    </node>")?;
+        }
+        for (i, edge) in self.edges.iter().enumerate() {
+            writeln!(
+                w,
+                r#"    <edge id="e{}" source="{}" target="{}">"#,
+                i,
+                escape(&edge.source),
+                escape(&edge.target)
+            )?;
+            writeln!(
+                w,
+                r#"      <data key="edge_kind">{}</data>"#,
+                edge.kind.as_str()
+            )?;
+            writeln!(w, r#"      <data key="edge_weight">{}</data>"#, edge.weight)?;
+            writeln!(w, "    </edge>")?;
+        }
+        writeln!(w, "  </graph>")?;
+        writeln!(w, "</graphml>")?;
+        w.flush()
+    }
+
+    fn write_gexf<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut w = BufWriter::new(writer);
+        writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(w, r#"<gexf xmlns="http://gexf.net/1.3" version="1.3">"#)?;
+        writeln!(w, r#"  <graph defaultedgetype="directed" mode="static">"#)?;
+        writeln!(w, r#"    <attributes class="node">"#)?;
+        let attributes = [
+            ("kind", "string"),
+            ("tweets", "long"),
+            ("favorites", "long"),
+            ("retweets", "long"),
+            ("created_at", "string"),
+            ("text", "string"),
+        ];
+        for (i, (name, kind)) in attributes.iter().enumerate() {
+            writeln!(
+                w,
+                r#"      <attribute id="{i}" title="{name}" type="{kind}"/>"#
+            )?;
+        }
+        writeln!(w, "    </attributes>")?;
+        writeln!(w, r#"    <attributes class="edge">"#)?;
+        writeln!(w, r#"      <attribute id="0" title="kind" type="string"/>"#)?;
+        writeln!(w, "    </attributes>")?;
+
+        writeln!(w, "    <nodes>")?;
+        for node in &self.nodes {
+            writeln!(
+                w,
+                r#"      <node id="{}" label="{}">"#,
+                escape(&node.id),
+                escape(&node.label)
+            )?;
+            writeln!(w, "        <attvalues>")?;
+            let values = [
+                Some(node.kind.as_str().to_string()),
+                Some(node.tweets.to_string()),
+                Some(node.favorites.to_string()),
+                Some(node.retweets.to_string()),
+                node.created_at.clone(),
+                node.text.clone(),
+            ];
+            for (i, value) in values.into_iter().enumerate() {
+                if let Some(value) = value {
+                    writeln!(
+                        w,
+                        r#"          <attvalue for="{}" value="{}"/>"#,
+                        i,
+                        escape(&value)
+                    )?;
+                }
+            }
+            writeln!(w, "        </attvalues>")?;
+            writeln!(w, "      </node>")?;
+        }
+        writeln!(w, "    </nodes>")?;
+
+        writeln!(w, "    <edges>")?;
+        for (i, edge) in self.edges.iter().enumerate() {
+            writeln!(
+                w,
+                r#"      <edge id="{}" source="{}" target="{}" weight="{}">"#,
+                i,
+                escape(&edge.source),
+                escape(&edge.target),
+                edge.weight
+            )?;
+            writeln!(
+                w,
+                r#"        <attvalues><attvalue for="0" value="{}"/></attvalues>"#,
+                edge.kind.as_str()
+            )?;
+            writeln!(w, "      </edge>")?;
+        }
+        writeln!(w, "    </edges>")?;
+        writeln!(w, "  </graph>")?;
+        writeln!(w, "</gexf>")?;
+        w.flush()
+    }
+
+    fn write_edge_list<W: Write>(&self, writer: W) -> csv::Result<()> {
+        let mut csv = csv::Writer::from_writer(writer);
+        csv.write_record(["Source", "Target", "Label", "Weight"])?;
+        for edge in &self.edges {
+            csv.write_record([
+                edge.source.as_str(),
+                edge.target.as_str(),
+                edge.kind.as_str(),
+                &edge.weight.to_string(),
+            ])?;
+        }
+        csv.flush()?;
+        Ok(())
+    }
+}
+
+// escapes text for XML content and attribute values, dropping the control
+// characters XML 1.0 cannot represent at all
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // keep newlines in attribute values from being normalized to spaces
+            '\n' => escaped.push_str("&#10;"),
+            '\r' => escaped.push_str("&#13;"),
+            '\t' => escaped.push_str("&#9;"),
+            c if c < ' ' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tweet(id: &str, user: &str, retweet_from: Option<&str>, favorites: u32) -> Tweet {
+        Tweet {
+            id: id.to_string(),
+            user: user.to_string(),
+            retweet_from_user: retweet_from.map(str::to_string),
+            favorite_count: favorites,
+            text: format!("<{}> & more", id),
+            ..Tweet::default()
+        }
+    }
+
+    fn sample() -> Vec<Tweet> {
+        vec![
+            tweet("1", "a", Some("b"), 2),
+            tweet("2", "a", Some("b"), 3),
+            tweet("3", "b", None, 1),
+            tweet("3", "b", None, 4),
+        ]
+    }
+
+    #[test]
+    fn test_users_carry_totals_and_retweets_are_weighted() {
+        let graph = ExportGraph::from_tweets(&sample(), false);
+
+        let a = &graph.nodes[0];
+        assert_eq!((a.label.as_str(), a.tweets, a.favorites), ("a", 2, 5));
+        // the repeated tweet 3 is counted once, as its last row
+        let b = &graph.nodes[1];
+        assert_eq!((b.label.as_str(), b.tweets, b.favorites), ("b", 1, 4));
+        assert_eq!(
+            graph.edges,
+            vec![Edge {
+                source: "u:a".to_string(),
+                target: "u:b".to_string(),
+                kind: EdgeKind::Retweeted,
+                weight: 2,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_xml_formats_escape_text() {
+        let graph = ExportGraph::from_tweets(&sample(), true);
+        assert_eq!(graph.nodes.len(), 5);
+        assert_eq!(graph.edges.len(), 4);
+
+        for format in [ExportFormat::GraphMl, ExportFormat::Gexf] {
+            let mut out = Vec::new();
+            graph.write(format, &mut out).unwrap();
+            let xml = String::from_utf8(out).unwrap();
+            assert!(xml.contains("&lt;1&gt; &amp; more"), "{:?}", format);
+            assert!(!xml.contains("<1>"));
+        }
+    }
+
+    #[test]
+    fn test_edge_list_has_gephi_headers() {
+        let graph = ExportGraph::from_tweets(&sample(), false);
+        let mut out = Vec::new();
+        graph.write(ExportFormat::EdgeList, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Source,Target,Label,Weight\nu:a,u:b,RETWEETED,2\n"
+        );
+    }
+}
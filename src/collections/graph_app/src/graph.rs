@@ -0,0 +1,271 @@
+// Storage-agnostic view of the tweet graph.
+//
+// The graph has `User` and `Tweet` nodes, `(User)-[:TWEETED]->(Tweet)` edges and
+// `(User)-[:RETWEETED]->(User)` edges. In Neo4j tweets also link to the users
+// they mention and to `Hashtag` and `Link` nodes. `TweetGraph` covers loading it
+// and the analyses we run over it, so the same code can target Neo4j or run
+// entirely in process against a CSV export.
+use crate::tweet::{Tweet, latest_by_id};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+mod memory;
+mod neo4j;
+
+pub use memory::InMemoryGraph;
+pub use neo4j::{BulkConfig, Neo4jGraph};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UserCount {
+    pub user: String,
+    pub count: usize,
+}
+
+/// Throughput of one `insert_tweets` call.
+#[derive(Debug, Clone, Default)]
+pub struct InsertStats {
+    /// Tweets written, one per distinct id; earlier rows for an id are skipped.
+    pub tweets: usize,
+    pub batches: usize,
+    pub elapsed: Duration,
+}
+
+impl InsertStats {
+    pub fn tweets_per_sec(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.tweets as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+impl fmt::Display for InsertStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "inserted {} tweets in {} batches in {:.2}s ({:.0} tweets/s)",
+            self.tweets,
+            self.batches,
+            self.elapsed.as_secs_f64(),
+            self.tweets_per_sec()
+        )
+    }
+}
+
+/// What one `migrate` call changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrateStats {
+    /// Retweet edges to the empty user, removed.
+    pub phantom_retweets: usize,
+    /// Users stored under a mixed-case handle, renamed to its lowercase form
+    /// or merged into the user already there.
+    pub lowercased_users: usize,
+    /// Tweets given the tweet_id of the export row they were loaded from.
+    pub backfilled_ids: usize,
+    /// Tweets removed as copies of one that already had their id.
+    pub duplicates: usize,
+    /// Tweets without an id that no export row matched, left as they were.
+    pub unmatched: usize,
+}
+
+impl fmt::Display for MigrateStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "removed {} phantom retweet edges, lowercased {} users, gave {} tweets an id, \
+             removed {} duplicate tweets, left {} unmatched tweets",
+            self.phantom_retweets,
+            self.lowercased_users,
+            self.backfilled_ids,
+            self.duplicates,
+            self.unmatched
+        )
+    }
+}
+
+/// How many of one kind of node or edge an insert would create, and how many
+/// it would find already in the graph.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Change {
+    pub created: usize,
+    pub matched: usize,
+}
+
+impl Change {
+    // `matched` counts nodes, and before the uniqueness constraints exist a
+    // key can match more than one, so it may pass `total`
+    fn new(total: usize, matched: usize) -> Self {
+        Change {
+            created: total.saturating_sub(matched),
+            matched,
+        }
+    }
+}
+
+/// The outcome of an insert, worked out without writing anything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InsertPlan {
+    pub tweets: Change,
+    pub users: Change,
+    pub hashtags: Change,
+    pub links: Change,
+    /// `RETWEETED` edges between users.
+    pub retweets: Change,
+    /// `TWEETED`, `MENTIONS`, `TAGGED` and `LINKS_TO` edges, which hang off a
+    /// tweet and so are new exactly when the tweet is.
+    pub tweet_edges: Change,
+}
+
+impl fmt::Display for InsertPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "dry run, nothing written:")?;
+        let rows = [
+            ("tweets", self.tweets),
+            ("users", self.users),
+            ("hashtags", self.hashtags),
+            ("links", self.links),
+            ("retweet edges", self.retweets),
+            ("tweet edges", self.tweet_edges),
+        ];
+        for (name, change) in rows {
+            write!(
+                f,
+                "\n  {}: {} to create, {} already present",
+                name, change.created, change.matched
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// The distinct nodes and edges an insert merges, keyed as the graph keys them.
+struct InsertKeys<'a> {
+    tweets: BTreeSet<&'a str>,
+    users: BTreeSet<&'a str>,
+    hashtags: BTreeSet<&'a str>,
+    links: BTreeSet<&'a str>,
+    retweets: BTreeSet<(&'a str, &'a str)>,
+    // edges hanging off each distinct tweet
+    tweet_edges: Vec<(&'a str, usize)>,
+}
+
+// What the graph already holds out of a set of `InsertKeys`.
+#[derive(Debug, Default)]
+struct Existing {
+    tweets: HashSet<String>,
+    users: usize,
+    hashtags: usize,
+    links: usize,
+    retweets: usize,
+}
+
+impl<'a> InsertKeys<'a> {
+    fn from_tweets(tweets: &'a [Tweet]) -> Self {
+        let mut keys = InsertKeys {
+            tweets: BTreeSet::new(),
+            users: BTreeSet::new(),
+            hashtags: BTreeSet::new(),
+            links: BTreeSet::new(),
+            retweets: BTreeSet::new(),
+            tweet_edges: Vec::new(),
+        };
+        for tweet in latest_by_id(tweets) {
+            keys.users.insert(&tweet.user);
+            keys.users.extend(tweet.mentions.iter().map(String::as_str));
+            keys.hashtags
+                .extend(tweet.hashtags.iter().map(String::as_str));
+            keys.links.extend(tweet.urls.iter().map(String::as_str));
+            if let Some(retweeted) = &tweet.retweet_from_user {
+                keys.users.insert(retweeted);
+                keys.retweets.insert((&tweet.user, retweeted));
+            }
+            keys.tweets.insert(&tweet.id);
+            let edges = 1 + tweet.mentions.len() + tweet.hashtags.len() + tweet.urls.len();
+            keys.tweet_edges.push((&tweet.id, edges));
+        }
+        keys
+    }
+
+    fn plan(&self, existing: &Existing) -> InsertPlan {
+        let (mut new_edges, mut old_edges) = (0, 0);
+        for (id, edges) in &self.tweet_edges {
+            if existing.tweets.contains(*id) {
+                old_edges += edges;
+            } else {
+                new_edges += edges;
+            }
+        }
+        InsertPlan {
+            tweets: Change::new(self.tweets.len(), existing.tweets.len()),
+            users: Change::new(self.users.len(), existing.users),
+            hashtags: Change::new(self.hashtags.len(), existing.hashtags),
+            links: Change::new(self.links.len(), existing.links),
+            retweets: Change::new(self.retweets.len(), existing.retweets),
+            tweet_edges: Change {
+                created: new_edges,
+                matched: old_edges,
+            },
+        }
+    }
+}
+
+pub trait TweetGraph {
+    /// Adds each tweet, its author and, for retweets, the author's edge to the
+    /// retweeted user. Everything is merged, tweets by id, so loading the same
+    /// tweets twice leaves the graph as it was after the first load. A tweet id
+    /// given again, in the same call or a later one, takes the fields and tweet
+    /// edges of its last row; users and retweet edges between them are only
+    /// ever added. Returns how long the insert took, for throughput reporting.
+    fn insert_tweets(
+        &mut self,
+        tweets: &[Tweet],
+    ) -> impl Future<Output = anyhow::Result<InsertStats>> + Send;
+
+    /// What `insert_tweets` would create and what it would find already there.
+    fn plan_insert(
+        &self,
+        tweets: &[Tweet],
+    ) -> impl Future<Output = anyhow::Result<InsertPlan>> + Send;
+
+    /// Brings a graph written by an older loader up to date. Original tweets used
+    /// to link their author to a user with an empty id, as though they were
+    /// retweets of it; that user and its edges are removed. Handles were stored
+    /// as written; each user is lowercased, or merged into the lowercase user a
+    /// later load created. Tweets were created without a `tweet_id`; each is
+    /// given the id of the row in `source`, the export it was loaded from, with
+    /// the same author, second and text, so reloading that export matches it.
+    /// Copies left by loading it twice are removed, and tweets no row matches
+    /// are left without an id.
+    fn migrate(
+        &mut self,
+        source: &[Tweet],
+    ) -> impl Future<Output = anyhow::Result<MigrateStats>> + Send;
+
+    /// Users who retweeted more than `min_retweeted` distinct accounts, most first.
+    fn retweet_rings(
+        &self,
+        min_retweeted: usize,
+    ) -> impl Future<Output = anyhow::Result<Vec<UserCount>>> + Send;
+
+    /// Up to `limit` chains of users joined by `min_hops..=max_hops` retweet
+    /// edges, followed in either direction.
+    fn retweet_paths(
+        &self,
+        min_hops: usize,
+        max_hops: usize,
+        limit: usize,
+    ) -> impl Future<Output = anyhow::Result<Vec<Vec<String>>>> + Send;
+
+    /// The `limit` users with the most consecutive tweets posted less than
+    /// `max_gap_secs` apart, most first.
+    fn rapid_fire(
+        &self,
+        max_gap_secs: i64,
+        limit: usize,
+    ) -> impl Future<Output = anyhow::Result<Vec<UserCount>>> + Send;
+}
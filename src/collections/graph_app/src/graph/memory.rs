@@ -0,0 +1,368 @@
+// In-process `TweetGraph` backed by adjacency maps.
+//
+// Mirrors what the Neo4j backend stores closely enough for the analyses to give
+// the same answers, so they can be run (and tested) without a database. Maps are
+// ordered so results, including ties, come out the same on every run.
+use super::{Existing, InsertKeys, InsertPlan, InsertStats, MigrateStats, TweetGraph, UserCount};
+use crate::tweet::{Tweet, latest_by_id};
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Instant;
+
+#[derive(Debug, Default)]
+pub struct InMemoryGraph {
+    // user -> users they retweeted; every known user has an entry
+    retweeted: BTreeMap<String, BTreeSet<String>>,
+    // tweet id -> author and posting time, as of the tweet's last row
+    tweets: BTreeMap<String, (String, DateTime<Utc>)>,
+    hashtags: BTreeSet<String>,
+    links: BTreeSet<String>,
+}
+
+impl InMemoryGraph {
+    pub fn new() -> Self {
+        InMemoryGraph::default()
+    }
+
+    // undirected adjacency; each neighbour is paired with the id of the edge
+    // leading to it so a path can be kept from reusing a relationship
+    fn undirected(&self) -> BTreeMap<&str, Vec<(&str, usize)>> {
+        let mut adjacency: BTreeMap<&str, Vec<(&str, usize)>> = BTreeMap::new();
+        let edges = self
+            .retweeted
+            .iter()
+            .flat_map(|(from, to)| to.iter().map(move |to| (from.as_str(), to.as_str())));
+        for (id, (from, to)) in edges.enumerate() {
+            adjacency.entry(from).or_default().push((to, id));
+            if from != to {
+                adjacency.entry(to).or_default().push((from, id));
+            }
+        }
+        adjacency
+    }
+}
+
+impl TweetGraph for InMemoryGraph {
+    async fn insert_tweets(&mut self, tweets: &[Tweet]) -> anyhow::Result<InsertStats> {
+        let start = Instant::now();
+        let mut sent = 0;
+        for tweet in latest_by_id(tweets) {
+            sent += 1;
+            for user in tweet.retweet_from_user.iter().chain(&tweet.mentions) {
+                self.retweeted.entry(user.clone()).or_default();
+            }
+            let retweeted = self.retweeted.entry(tweet.user.clone()).or_default();
+            if let Some(user) = &tweet.retweet_from_user {
+                retweeted.insert(user.clone());
+            }
+            self.hashtags.extend(tweet.hashtags.iter().cloned());
+            self.links.extend(tweet.urls.iter().cloned());
+            // a tweet already in the graph is replaced, as Neo4j's SET does
+            self.tweets
+                .insert(tweet.id.clone(), (tweet.user.clone(), tweet.created_at));
+        }
+        Ok(InsertStats {
+            tweets: sent,
+            batches: 1,
+            elapsed: start.elapsed(),
+        })
+    }
+
+    async fn plan_insert(&self, tweets: &[Tweet]) -> anyhow::Result<InsertPlan> {
+        let keys = InsertKeys::from_tweets(tweets);
+        let existing = Existing {
+            tweets: keys
+                .tweets
+                .iter()
+                .filter(|id| self.tweets.contains_key(**id))
+                .map(|id| id.to_string())
+                .collect(),
+            users: keys
+                .users
+                .iter()
+                .filter(|user| self.retweeted.contains_key(**user))
+                .count(),
+            hashtags: keys
+                .hashtags
+                .iter()
+                .filter(|tag| self.hashtags.contains(**tag))
+                .count(),
+            links: keys
+                .links
+                .iter()
+                .filter(|url| self.links.contains(**url))
+                .count(),
+            retweets: keys
+                .retweets
+                .iter()
+                .filter(|(from, to)| self.retweeted.get(*from).is_some_and(|r| r.contains(*to)))
+                .count(),
+        };
+        Ok(keys.plan(&existing))
+    }
+
+    async fn migrate(&mut self, _source: &[Tweet]) -> anyhow::Result<MigrateStats> {
+        // an in-memory graph never outlives the loader that built it
+        Ok(MigrateStats::default())
+    }
+
+    async fn retweet_rings(&self, min_retweeted: usize) -> anyhow::Result<Vec<UserCount>> {
+        let mut rings: Vec<UserCount> = self
+            .retweeted
+            .iter()
+            .filter(|(_, accounts)| accounts.len() > min_retweeted)
+            .map(|(user, accounts)| UserCount {
+                user: user.clone(),
+                count: accounts.len(),
+            })
+            .collect();
+        rings.sort_by_key(|r| std::cmp::Reverse(r.count));
+        Ok(rings)
+    }
+
+    async fn retweet_paths(
+        &self,
+        min_hops: usize,
+        max_hops: usize,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Vec<String>>> {
+        let adjacency = self.undirected();
+        let mut paths = Vec::new();
+        let mut path = Vec::new();
+        let mut used_edges = BTreeSet::new();
+        for &start in adjacency.keys() {
+            if paths.len() >= limit {
+                break;
+            }
+            path.push(start);
+            walk(
+                &adjacency,
+                &mut path,
+                &mut used_edges,
+                (min_hops, max_hops),
+                limit,
+                &mut paths,
+            );
+            path.pop();
+        }
+        Ok(paths)
+    }
+
+    async fn rapid_fire(&self, max_gap_secs: i64, limit: usize) -> anyhow::Result<Vec<UserCount>> {
+        let mut tweet_times: BTreeMap<&str, Vec<DateTime<Utc>>> = BTreeMap::new();
+        for (user, created_at) in self.tweets.values() {
+            tweet_times.entry(user).or_default().push(*created_at);
+        }
+        let mut users: Vec<UserCount> = tweet_times
+            .into_iter()
+            .map(|(user, mut times)| {
+                times.sort();
+                let count = times
+                    .windows(2)
+                    .filter(|pair| (pair[1] - pair[0]).num_seconds() < max_gap_secs)
+                    .count();
+                UserCount {
+                    user: user.to_string(),
+                    count,
+                }
+            })
+            .filter(|u| u.count > 0)
+            .collect();
+        users.sort_by_key(|u| std::cmp::Reverse(u.count));
+        users.truncate(limit);
+        Ok(users)
+    }
+}
+
+// depth-first enumeration of relationship-unique paths, as Cypher matches them
+fn walk<'a>(
+    adjacency: &BTreeMap<&'a str, Vec<(&'a str, usize)>>,
+    path: &mut Vec<&'a str>,
+    used_edges: &mut BTreeSet<usize>,
+    (min_hops, max_hops): (usize, usize),
+    limit: usize,
+    paths: &mut Vec<Vec<String>>,
+) {
+    let hops = path.len() - 1;
+    if hops >= min_hops && hops > 0 {
+        paths.push(path.iter().map(|u| u.to_string()).collect());
+    }
+    if hops == max_hops {
+        return;
+    }
+    let current = path[hops];
+    for &(next, edge) in adjacency.get(current).into_iter().flatten() {
+        if paths.len() >= limit {
+            return;
+        }
+        if !used_edges.insert(edge) {
+            continue;
+        }
+        path.push(next);
+        walk(
+            adjacency,
+            path,
+            used_edges,
+            (min_hops, max_hops),
+            limit,
+            paths,
+        );
+        path.pop();
+        used_edges.remove(&edge);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Change;
+    use chrono::TimeZone;
+
+    // an empty `retweet_from` makes an original tweet
+    fn tweet(user: &str, secs: i64, retweet_from: &str) -> Tweet {
+        Tweet {
+            id: format!("{}-{}", user, secs),
+            user: user.to_string(),
+            created_at: Utc.timestamp_opt(secs, 0).unwrap(),
+            retweet_from_user: Some(retweet_from.to_string()).filter(|u| !u.is_empty()),
+            ..Tweet::default()
+        }
+    }
+
+    async fn graph(tweets: &[Tweet]) -> InMemoryGraph {
+        let mut graph = InMemoryGraph::new();
+        graph.insert_tweets(tweets).await.unwrap();
+        graph
+    }
+
+    #[tokio::test]
+    async fn test_retweet_rings_count_distinct_accounts() {
+        let graph = graph(&[
+            tweet("a", 0, "x"),
+            tweet("a", 1, "x"),
+            tweet("a", 2, "y"),
+            tweet("a", 3, "z"),
+            tweet("b", 4, "x"),
+            tweet("b", 5, "y"),
+        ])
+        .await;
+
+        let rings = graph.retweet_rings(1).await.unwrap();
+        assert_eq!(
+            rings,
+            vec![
+                UserCount {
+                    user: "a".to_string(),
+                    count: 3
+                },
+                UserCount {
+                    user: "b".to_string(),
+                    count: 2
+                },
+            ]
+        );
+        assert!(graph.retweet_rings(3).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_retweet_paths_follow_edges_both_ways() {
+        // a -> b <- c -> d
+        let graph = graph(&[tweet("a", 0, "b"), tweet("c", 0, "b"), tweet("c", 1, "d")]).await;
+
+        let paths = graph.retweet_paths(3, 5, 20).await.unwrap();
+        assert!(paths.contains(&vec![
+            "a".to_string(),
+            "b".to_string(),
+            "c".to_string(),
+            "d".to_string()
+        ]));
+        assert!(paths.iter().all(|p| p.len() == 4));
+        assert_eq!(graph.retweet_paths(3, 5, 1).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rapid_fire_counts_short_gaps() {
+        let graph = graph(&[
+            tweet("a", 100, "x"),
+            tweet("a", 0, "x"),
+            tweet("a", 10, "x"),
+            tweet("a", 20, "x"),
+            tweet("b", 0, "x"),
+            tweet("b", 29, "x"),
+            tweet("c", 0, "x"),
+            tweet("c", 30, "x"),
+        ])
+        .await;
+
+        let users = graph.rapid_fire(30, 10).await.unwrap();
+        assert_eq!(
+            users,
+            vec![
+                UserCount {
+                    user: "a".to_string(),
+                    count: 2
+                },
+                UserCount {
+                    user: "b".to_string(),
+                    count: 1
+                },
+            ]
+        );
+        assert_eq!(graph.rapid_fire(30, 1).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_repeated_tweet_id_keeps_its_last_row() {
+        let row = |user: &str, secs: i64| Tweet {
+            id: "t".to_string(),
+            ..tweet(user, secs, "")
+        };
+        let mut graph = graph(&[row("a", 0), row("a", 10), tweet("a", 20, "")]).await;
+        // a's tweets are at 10 and 20, not 0 and 20
+        assert_eq!(
+            graph.rapid_fire(15, 10).await.unwrap(),
+            vec![UserCount {
+                user: "a".to_string(),
+                count: 1
+            }]
+        );
+
+        // a later load moves the tweet to another author
+        let stats = graph
+            .insert_tweets(&[row("b", 90), row("b", 100)])
+            .await
+            .unwrap();
+        assert_eq!(stats.tweets, 1);
+        assert!(graph.rapid_fire(15, 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reloading_is_idempotent_and_planned_as_matches() {
+        let first = [tweet("a", 0, "b"), tweet("a", 10, "")];
+        let mut graph = graph(&first).await;
+
+        let mut second = first.to_vec();
+        second.push(Tweet {
+            hashtags: vec!["new".to_string()],
+            ..tweet("c", 20, "b")
+        });
+        let plan = graph.plan_insert(&second).await.unwrap();
+        let counts = |change: Change| (change.created, change.matched);
+        assert_eq!(counts(plan.tweets), (1, 2));
+        assert_eq!(counts(plan.users), (1, 2));
+        assert_eq!(counts(plan.hashtags), (1, 0));
+        // the original tweet by `a` has no retweet edge
+        assert_eq!(counts(plan.retweets), (1, 1));
+        assert_eq!(counts(plan.tweet_edges), (2, 2));
+
+        graph.insert_tweets(&first).await.unwrap();
+        let users = graph.rapid_fire(60, 10).await.unwrap();
+        assert_eq!(
+            users,
+            vec![UserCount {
+                user: "a".to_string(),
+                count: 1
+            }]
+        );
+    }
+}
@@ -0,0 +1,591 @@
+use super::{Existing, InsertKeys, InsertPlan, InsertStats, MigrateStats, TweetGraph, UserCount};
+use crate::tweet::{Tweet, handle_key, latest_by_id};
+use neo4rs::{BoltList, BoltMap, BoltType, Graph, Path, query};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::task::JoinSet;
+
+// One statement per batch: the rows travel as a single list parameter. Tweets
+// are upserted by id, so counts that changed since the last load are updated,
+// and the edges an earlier load hung off the tweet are replaced by this row's.
+const INSERT_BATCH: &str = "UNWIND $rows AS row \
+    MERGE (u:User {user_id: row.user_id}) \
+    MERGE (t:Tweet {tweet_id: row.tweet_id}) \
+    FOREACH (old IN [(t)-[r:MENTIONS|TAGGED|LINKS_TO]->() | r] | DELETE old) \
+    FOREACH (old IN [(a:User)-[r:TWEETED]->(t) WHERE a.user_id <> row.user_id | r] | DELETE old) \
+    SET t.text = row.text, \
+        t.created_at = row.created_at, \
+        t.retweet_count = row.retweet_count, \
+        t.retweeted = row.retweeted, \
+        t.favorite_count = row.favorite_count, \
+        t.retweet_from_user = row.retweet_from_user, \
+        t.reply_to = row.reply_to, \
+        t.quote_of = row.quote_of \
+    MERGE (u)-[:TWEETED]->(t) \
+    FOREACH (source IN CASE WHEN row.retweet_from_user IS NULL THEN [] ELSE [row.retweet_from_user] END | \
+        MERGE (r:User {user_id: source}) MERGE (u)-[:RETWEETED]->(r)) \
+    FOREACH (m IN row.mentions | MERGE (mu:User {user_id: m}) MERGE (t)-[:MENTIONS]->(mu)) \
+    FOREACH (tag IN row.hashtags | MERGE (h:Hashtag {name: tag}) MERGE (t)-[:TAGGED]->(h)) \
+    FOREACH (url IN row.urls | MERGE (l:Link {url: url}) MERGE (t)-[:LINKS_TO]->(l))";
+
+// Concurrent MERGEs only stay unique, and only stay fast on large graphs, when
+// the merged keys are backed by a constraint.
+const CONSTRAINTS: [&str; 4] = [
+    "CREATE CONSTRAINT tweet_id IF NOT EXISTS FOR (t:Tweet) REQUIRE t.tweet_id IS UNIQUE",
+    "CREATE CONSTRAINT user_id IF NOT EXISTS FOR (u:User) REQUIRE u.user_id IS UNIQUE",
+    "CREATE CONSTRAINT hashtag_name IF NOT EXISTS FOR (h:Hashtag) REQUIRE h.name IS UNIQUE",
+    "CREATE CONSTRAINT link_url IF NOT EXISTS FOR (l:Link) REQUIRE l.url IS UNIQUE",
+];
+
+#[derive(Debug, Clone)]
+pub struct BulkConfig {
+    /// Tweets sent per statement.
+    pub batch_size: usize,
+    /// Batches in flight at once, each in its own transaction.
+    ///
+    /// Batches MERGE the same users and hashtags, so concurrent ones can take
+    /// their locks in different orders and deadlock. The driver retries
+    /// transient errors such as `DeadlockDetected` with backoff for up to a
+    /// minute, but heavy contention can outlast that and fail the load, so
+    /// the default is one batch at a time.
+    pub workers: usize,
+}
+
+impl Default for BulkConfig {
+    fn default() -> Self {
+        BulkConfig {
+            batch_size: 1000,
+            workers: 1,
+        }
+    }
+}
+
+pub struct Neo4jGraph {
+    graph: Arc<Graph>,
+    bulk: BulkConfig,
+}
+
+impl Neo4jGraph {
+    pub async fn connect(uri: &str, user: &str, password: &str) -> anyhow::Result<Self> {
+        let graph = Graph::new(uri, user, password).await?;
+        Ok(Neo4jGraph {
+            graph: Arc::new(graph),
+            bulk: BulkConfig::default(),
+        })
+    }
+
+    pub fn with_bulk_config(mut self, bulk: BulkConfig) -> Self {
+        self.bulk = bulk;
+        self
+    }
+
+    // runs a statement ending in `RETURN count(..) AS n` until it reports zero,
+    // returning the sum of the counts
+    async fn repeat_until_done(&self, cypher: &str) -> anyhow::Result<usize> {
+        let mut total = 0;
+        loop {
+            let mut result = self
+                .graph
+                .execute(query(cypher).param("batch", self.bulk.batch_size.max(1) as i64))
+                .await?;
+            let mut n = 0;
+            while let Some(row) = result.next().await? {
+                n += row.get::<i64>("n")? as usize;
+            }
+            if n == 0 {
+                return Ok(total);
+            }
+            total += n;
+        }
+    }
+
+    // runs `cypher` over `keys` a batch at a time, returning the `n` column of
+    // each batch's single row
+    async fn count_existing(&self, cypher: &str, keys: Vec<BoltType>) -> anyhow::Result<usize> {
+        let mut total = 0;
+        for batch in keys.chunks(self.bulk.batch_size.max(1)) {
+            let mut result = self
+                .graph
+                .execute(query(cypher).param("keys", batch.to_vec()))
+                .await?;
+            while let Some(row) = result.next().await? {
+                total += row.get::<i64>("n")? as usize;
+            }
+        }
+        Ok(total)
+    }
+}
+
+fn str_keys<'a>(keys: impl IntoIterator<Item = &'a &'a str>) -> Vec<BoltType> {
+    keys.into_iter().map(|key| BoltType::from(*key)).collect()
+}
+
+fn strings(items: &[String]) -> BoltType {
+    BoltType::List(
+        items
+            .iter()
+            .map(|s| BoltType::from(s.as_str()))
+            .collect::<Vec<_>>()
+            .into(),
+    )
+}
+
+// Users the old loader stored under a handle as written. Each is folded into
+// the lowercase user a load now creates, taking its edges along, or renamed
+// when there is no such user yet; one of each set of variants is renamed per
+// pass, so the rest are merged into it on the next.
+const MERGE_USERS: &str = "MATCH (old:User) WHERE old.user_id <> toLower(old.user_id) \
+    MATCH (kept:User {user_id: toLower(old.user_id)}) \
+    WITH old, kept LIMIT $batch \
+    FOREACH (t IN [(old)-[:TWEETED]->(t:Tweet) | t] | MERGE (kept)-[:TWEETED]->(t)) \
+    FOREACH (u IN [(old)-[:RETWEETED]->(u:User) WHERE u <> old | u] | MERGE (kept)-[:RETWEETED]->(u)) \
+    FOREACH (u IN [(u:User)-[:RETWEETED]->(old) WHERE u <> old | u] | MERGE (u)-[:RETWEETED]->(kept)) \
+    FOREACH (t IN [(t:Tweet)-[:MENTIONS]->(old) | t] | MERGE (t)-[:MENTIONS]->(kept)) \
+    DETACH DELETE old \
+    RETURN count(*) AS n";
+const RENAME_USERS: &str = "MATCH (u:User) WHERE u.user_id <> toLower(u.user_id) \
+    AND NOT EXISTS { MATCH (:User {user_id: toLower(u.user_id)}) } \
+    WITH toLower(u.user_id) AS key, collect(u)[0] AS u LIMIT $batch \
+    SET u.user_id = key \
+    RETURN count(u) AS n";
+
+// Tweets written by the old loader, which had no tweet_id, after the node id
+// `$after`. Its created_at is in whole seconds.
+const LEGACY_TWEETS: &str = "MATCH (u:User)-[:TWEETED]->(t:Tweet) \
+    WHERE t.tweet_id IS NULL AND id(t) > $after \
+      AND t.created_at IS NOT NULL AND t.text IS NOT NULL \
+    RETURN id(t) AS node, u.user_id AS user, t.created_at AS created_at, t.text AS text \
+    ORDER BY node LIMIT $batch";
+
+// Gives each legacy tweet its id, unless another tweet already has it, in which
+// case the legacy tweet is a copy and is removed.
+const BACKFILL_IDS: &str = "UNWIND $rows AS row \
+    MATCH (t:Tweet) WHERE id(t) = row.node \
+    OPTIONAL MATCH (kept:Tweet {tweet_id: row.tweet_id}) \
+    FOREACH (_ IN CASE WHEN kept IS NULL THEN [1] ELSE [] END | SET t.tweet_id = row.tweet_id) \
+    FOREACH (_ IN CASE WHEN kept IS NULL THEN [] ELSE [1] END | DETACH DELETE t) \
+    RETURN count(kept) AS n";
+
+// The rows of an export, looked up the way the old loader stored them: by
+// author and created_at in seconds, with only the first stretch of the text.
+struct LegacySource<'a> {
+    by_time: HashMap<(String, i64), Vec<&'a Tweet>>,
+}
+
+impl<'a> LegacySource<'a> {
+    fn new(tweets: &'a [Tweet]) -> Self {
+        let mut by_time: HashMap<_, Vec<_>> = HashMap::new();
+        for tweet in latest_by_id(tweets) {
+            let key = (tweet.user.clone(), tweet.created_at.timestamp());
+            by_time.entry(key).or_default().push(tweet);
+        }
+        LegacySource { by_time }
+    }
+
+    // The id of the one row a legacy tweet was created from, if there is one.
+    // The old loader split lines on every comma, so its text is the raw CSV
+    // field cut at the first comma, opening quote included, and only the first
+    // line of a multi-line tweet. Up to its first quote after that, it is a
+    // prefix of the real text.
+    fn tweet_id(&self, user: &str, created_at: i64, text: &str) -> Option<&'a str> {
+        let candidates = self.by_time.get(&(handle_key(user), created_at))?;
+        let text = text.strip_prefix('"').unwrap_or(text);
+        let prefix = text.split('"').next().unwrap_or_default();
+        let mut matches = candidates
+            .iter()
+            .filter(|tweet| tweet.text.starts_with(prefix));
+        match (matches.next(), matches.next()) {
+            (Some(tweet), None) => Some(&tweet.id),
+            _ => None,
+        }
+    }
+}
+
+// The row map for one tweet. Tweets stay borrowed, batches being slices of
+// references, up to here: Bolt values own their strings, so this is where a
+// tweet's fields are copied, one batch at a time as it is sent.
+fn tweet_row(tweet: &Tweet) -> BoltType {
+    let fields: [(&str, BoltType); 13] = [
+        ("tweet_id", tweet.id.as_str().into()),
+        ("user_id", tweet.user.as_str().into()),
+        ("text", tweet.text.as_str().into()),
+        ("created_at", tweet.created_at.timestamp().into()),
+        ("retweet_count", tweet.retweet_count.into()),
+        ("retweeted", tweet.retweeted.into()),
+        ("favorite_count", tweet.favorite_count.into()),
+        (
+            "retweet_from_user",
+            tweet.retweet_from_user.as_deref().into(),
+        ),
+        ("reply_to", tweet.reply_to.as_deref().into()),
+        ("quote_of", tweet.quote_of.as_deref().into()),
+        ("mentions", strings(&tweet.mentions)),
+        ("hashtags", strings(&tweet.hashtags)),
+        ("urls", strings(&tweet.urls)),
+    ];
+    BoltType::Map(
+        fields
+            .into_iter()
+            .map(|(key, value)| (key.into(), value))
+            .collect::<BoltMap>(),
+    )
+}
+
+impl TweetGraph for Neo4jGraph {
+    async fn insert_tweets(&mut self, tweets: &[Tweet]) -> anyhow::Result<InsertStats> {
+        let start = Instant::now();
+        for constraint in CONSTRAINTS {
+            self.graph.run(query(constraint)).await?;
+        }
+
+        let workers = self.bulk.workers.max(1);
+        let mut in_flight = JoinSet::new();
+        let mut batches = 0;
+        // only a tweet's last row survives its upsert, so it is the only one sent
+        let latest: Vec<&Tweet> = latest_by_id(tweets).collect();
+        for batch in latest.chunks(self.bulk.batch_size.max(1)) {
+            if in_flight.len() >= workers
+                && let Some(done) = in_flight.join_next().await
+            {
+                done??;
+            }
+            let rows: BoltList = batch
+                .iter()
+                .map(|tweet| tweet_row(tweet))
+                .collect::<Vec<_>>()
+                .into();
+            let graph = Arc::clone(&self.graph);
+            in_flight.spawn(async move {
+                graph
+                    .run(query(INSERT_BATCH).param("rows", BoltType::List(rows)))
+                    .await
+            });
+            batches += 1;
+        }
+        while let Some(done) = in_flight.join_next().await {
+            done??;
+        }
+
+        Ok(InsertStats {
+            tweets: latest.len(),
+            batches,
+            elapsed: start.elapsed(),
+        })
+    }
+
+    async fn plan_insert(&self, tweets: &[Tweet]) -> anyhow::Result<InsertPlan> {
+        let keys = InsertKeys::from_tweets(tweets);
+
+        let mut existing = Existing::default();
+        let ids: Vec<&str> = keys.tweets.iter().copied().collect();
+        for batch in ids.chunks(self.bulk.batch_size.max(1)) {
+            let mut result = self
+                .graph
+                .execute(
+                    query("UNWIND $keys AS key MATCH (t:Tweet {tweet_id: key}) RETURN key")
+                        .param("keys", batch),
+                )
+                .await?;
+            while let Some(row) = result.next().await? {
+                existing.tweets.insert(row.get("key")?);
+            }
+        }
+        existing.users = self
+            .count_existing(
+                "UNWIND $keys AS key MATCH (u:User {user_id: key}) RETURN count(u) AS n",
+                str_keys(&keys.users),
+            )
+            .await?;
+        existing.hashtags = self
+            .count_existing(
+                "UNWIND $keys AS key MATCH (h:Hashtag {name: key}) RETURN count(h) AS n",
+                str_keys(&keys.hashtags),
+            )
+            .await?;
+        existing.links = self
+            .count_existing(
+                "UNWIND $keys AS key MATCH (l:Link {url: key}) RETURN count(l) AS n",
+                str_keys(&keys.links),
+            )
+            .await?;
+        let pairs = keys
+            .retweets
+            .iter()
+            .map(|(from, to)| BoltType::from(vec![*from, *to]))
+            .collect();
+        existing.retweets = self
+            .count_existing(
+                "UNWIND $keys AS key \
+                 MATCH (:User {user_id: key[0]})-[:RETWEETED]->(:User {user_id: key[1]}) \
+                 RETURN count(DISTINCT key) AS n",
+                pairs,
+            )
+            .await?;
+
+        Ok(keys.plan(&existing))
+    }
+
+    async fn migrate(&mut self, source: &[Tweet]) -> anyhow::Result<MigrateStats> {
+        // edges go in batches so a large graph does not need one huge transaction
+        let phantom_retweets = self
+            .repeat_until_done(
+                "MATCH (:User {user_id: \"\"})<-[r:RETWEETED]-() \
+                 WITH r LIMIT $batch DELETE r RETURN count(r) AS n",
+            )
+            .await?;
+        self.graph
+            .run(query("MATCH (u:User {user_id: \"\"}) DETACH DELETE u"))
+            .await?;
+        self.repeat_until_done(
+            "MATCH (t:Tweet {retweet_from_user: \"\"}) \
+             WITH t LIMIT $batch REMOVE t.retweet_from_user RETURN count(t) AS n",
+        )
+        .await?;
+
+        let mut stats = MigrateStats {
+            phantom_retweets,
+            ..MigrateStats::default()
+        };
+        loop {
+            let merged = self.repeat_until_done(MERGE_USERS).await?;
+            let renamed = self.repeat_until_done(RENAME_USERS).await?;
+            stats.lowercased_users += merged + renamed;
+            if renamed == 0 {
+                break;
+            }
+        }
+
+        let source = LegacySource::new(source);
+        let batch_size = self.bulk.batch_size.max(1) as i64;
+        let mut after = -1;
+        loop {
+            let mut result = self
+                .graph
+                .execute(
+                    query(LEGACY_TWEETS)
+                        .param("after", after)
+                        .param("batch", batch_size),
+                )
+                .await?;
+            let mut rows = Vec::new();
+            let mut ids = HashSet::new();
+            let mut copies = Vec::new();
+            let mut fetched = 0;
+            while let Some(row) = result.next().await? {
+                let node: i64 = row.get("node")?;
+                let user: String = row.get("user")?;
+                let created_at: i64 = row.get("created_at")?;
+                let text: String = row.get("text")?;
+                (after, fetched) = (node, fetched + 1);
+                let Some(id) = source.tweet_id(&user, created_at, &text) else {
+                    stats.unmatched += 1;
+                    continue;
+                };
+                // copies within a batch are settled here, so no row waits on another
+                if ids.insert(id) {
+                    let fields = [("node", BoltType::from(node)), ("tweet_id", id.into())];
+                    rows.push(BoltType::Map(
+                        fields
+                            .into_iter()
+                            .map(|(key, value)| (key.into(), value))
+                            .collect::<BoltMap>(),
+                    ));
+                } else {
+                    copies.push(node);
+                }
+            }
+            if fetched == 0 {
+                return Ok(stats);
+            }
+
+            let batch = rows.len();
+            let mut existing = 0;
+            if !rows.is_empty() {
+                let mut result = self
+                    .graph
+                    .execute(query(BACKFILL_IDS).param("rows", rows))
+                    .await?;
+                while let Some(row) = result.next().await? {
+                    existing += row.get::<i64>("n")? as usize;
+                }
+            }
+            stats.backfilled_ids += batch - existing;
+            stats.duplicates += existing + copies.len();
+            if !copies.is_empty() {
+                let cypher =
+                    "UNWIND $nodes AS node MATCH (t:Tweet) WHERE id(t) = node DETACH DELETE t";
+                self.graph.run(query(cypher).param("nodes", copies)).await?;
+            }
+        }
+    }
+
+    async fn retweet_rings(&self, min_retweeted: usize) -> anyhow::Result<Vec<UserCount>> {
+        let cypher = "MATCH (u1:User)-[:RETWEETED]->(u2:User) WITH u1,\
+                      collect(u2.user_id) as retweeted_accounts, count(*) as n where n > $min \
+                      RETURN u1.user_id as user, n ORDER BY n DESC";
+        let mut result = self
+            .graph
+            .execute(query(cypher).param("min", min_retweeted as i64))
+            .await?;
+        let mut rings = Vec::new();
+        while let Some(row) = result.next().await? {
+            let user: String = row.get("user")?;
+            let n: i64 = row.get("n")?;
+            rings.push(UserCount {
+                user,
+                count: n as usize,
+            });
+        }
+        Ok(rings)
+    }
+
+    async fn retweet_paths(
+        &self,
+        min_hops: usize,
+        max_hops: usize,
+        limit: usize,
+    ) -> anyhow::Result<Vec<Vec<String>>> {
+        // variable-length bounds cannot be query parameters
+        let cypher = format!(
+            "MATCH path = (a:User)-[:RETWEETED*{}..{}]-(b:User) RETURN path LIMIT $limit",
+            min_hops, max_hops
+        );
+        let mut result = self
+            .graph
+            .execute(query(&cypher).param("limit", limit as i64))
+            .await?;
+        let mut paths = Vec::new();
+        while let Some(row) = result.next().await? {
+            let path: Path = row.get("path")?;
+            let mut user_ids = Vec::new();
+            for node in path.nodes() {
+                if node.labels().contains(&"User")
+                    && let Ok(user_id) = node.get::<String>("user_id")
+                {
+                    user_ids.push(user_id);
+                }
+            }
+            paths.push(user_ids);
+        }
+        Ok(paths)
+    }
+
+    async fn rapid_fire(&self, max_gap_secs: i64, limit: usize) -> anyhow::Result<Vec<UserCount>> {
+        let cypher = "MATCH (u:User)-[:TWEETED]->(t:Tweet) \
+                      WITH u, datetime({epochSeconds: t.created_at}) AS ts \
+                      ORDER BY u.user_id, ts WITH u, collect(ts) AS times \
+                      UNWIND range(0, size(times)-2) as i \
+                      WITH u, duration.inSeconds(times[i], times[i+1]) AS between \
+                      WHERE between.seconds < $max_gap \
+                      RETURN u.user_id as user, count(*) AS rapid_posts \
+                      ORDER BY rapid_posts DESC LIMIT $limit";
+        let mut result = self
+            .graph
+            .execute(
+                query(cypher)
+                    .param("max_gap", max_gap_secs)
+                    .param("limit", limit as i64),
+            )
+            .await?;
+        let mut users = Vec::new();
+        while let Some(row) = result.next().await? {
+            let user: String = row.get("user")?;
+            let rapid_posts: i64 = row.get("rapid_posts")?;
+            users.push(UserCount {
+                user,
+                count: rapid_posts as usize,
+            });
+        }
+        Ok(users)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::Change;
+
+    #[test]
+    fn test_tweet_row_carries_every_field_the_statement_reads() {
+        let tweet = Tweet {
+            user: "a".to_string(),
+            retweet_from_user: Some("b".to_string()),
+            hashtags: vec!["tag".to_string()],
+            reply_to: Some("c".to_string()),
+            ..Tweet::default()
+        };
+        let BoltType::Map(row) = tweet_row(&tweet) else {
+            panic!("row is not a map");
+        };
+
+        for key in INSERT_BATCH.split("row.").skip(1).map(|rest| {
+            rest.split(|c: char| !c.is_alphanumeric() && c != '_')
+                .next()
+                .unwrap()
+        }) {
+            assert!(row.value.contains_key(key), "missing {}", key);
+        }
+        assert_eq!(row.get::<String>("user_id").unwrap(), "a");
+        assert_eq!(row.get::<Vec<String>>("hashtags").unwrap(), vec!["tag"]);
+        assert_eq!(row.get::<Option<String>>("quote_of").unwrap(), None);
+    }
+
+    #[test]
+    fn test_duplicate_nodes_do_not_overflow_the_plan() {
+        // two User nodes for one key, as a graph loaded before the constraints
+        // may hold, count as two matches
+        let plan = InsertKeys::from_tweets(&[Tweet {
+            id: "t".to_string(),
+            user: "a".to_string(),
+            ..Tweet::default()
+        }])
+        .plan(&Existing {
+            users: 2,
+            ..Existing::default()
+        });
+        assert_eq!(
+            plan.users,
+            Change {
+                created: 0,
+                matched: 2
+            }
+        );
+    }
+
+    // what the old loader stored for a line of the export: the author as
+    // written, created_at in seconds and the eighth comma-separated field
+    fn legacy_row(line: &str) -> (String, i64, String) {
+        let parts: Vec<&str> = line.split(",").collect();
+        let millis: i64 = parts[2].parse().unwrap();
+        (parts[1].to_string(), millis / 1000, parts[7].to_string())
+    }
+
+    #[test]
+    fn test_legacy_tweets_are_matched_to_their_source_row() {
+        // shaped like data.csv: no header, the tweet_id in the ninth column
+        let data = concat!(
+            "1,Alice,1471272620000,2016-08-15 14:50:20,0,FALSE,0,",
+            "\"RT @bob: hi, \"\"all\"\"\",765198948239810561,,[],[],POSTED,[],,\n",
+            "1,Alice,1471272620500,2016-08-15 14:50:20,0,FALSE,0,",
+            "\"second,\nline two\",765198948239810562,,[],[],POSTED,[],,\n",
+            "1,Alice,1471272621000,2016-08-15 14:50:21,0,FALSE,0,",
+            "plain,765198948239810563,,[],[],POSTED,[],,\n",
+        );
+        let (tweets, _) = crate::loader::read_tweets(data.as_bytes()).unwrap();
+        let source = LegacySource::new(&tweets);
+
+        let lines: Vec<&str> = data.lines().collect();
+        for (line, id) in [
+            (lines[0], "765198948239810561"),
+            (lines[1], "765198948239810562"),
+            (lines[3], "765198948239810563"),
+        ] {
+            let (user, created_at, text) = legacy_row(line);
+            assert_eq!(source.tweet_id(&user, created_at, &text), Some(id));
+        }
+        // nothing in the export at that second, or nothing with that text
+        assert_eq!(source.tweet_id("alice", 1471272622, "plain"), None);
+        assert_eq!(source.tweet_id("alice", 1471272620, "other"), None);
+    }
+}
@@ -0,0 +1,6 @@
+pub mod analysis;
+pub mod config;
+pub mod export;
+pub mod graph;
+pub mod loader;
+pub mod tweet;
@@ -0,0 +1,251 @@
+// Reads a tweet export CSV into `Tweet`s.
+//
+// Parsing is done by the csv crate, so text with embedded commas, quotes and
+// newlines comes through intact. Columns are matched by header name; exports
+// without a header row are assumed to use `DEFAULT_COLUMNS`. Rows that cannot
+// be turned into a `Tweet` are skipped and listed in the returned `LoadReport`.
+use crate::tweet::{Tweet, handle_key};
+use anyhow::{Context, bail};
+use chrono::{TimeZone, Utc};
+use csv::{ReaderBuilder, StringRecord};
+use serde::Deserialize;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+// column order of the troll tweet export in data.csv, which has no header row
+pub const DEFAULT_COLUMNS: [&str; 16] = [
+    "user_id",
+    "user_key",
+    "created_at",
+    "created_str",
+    "retweet_count",
+    "retweeted",
+    "favorite_count",
+    "text",
+    "tweet_id",
+    "source",
+    "hashtags",
+    "expanded_urls",
+    "posted",
+    "mentions",
+    "retweeted_status_id",
+    "in_reply_to_status_id",
+];
+
+const REQUIRED_COLUMNS: [&str; 3] = ["user_key", "created_at", "text"];
+
+#[derive(Debug, Deserialize)]
+struct TweetRow {
+    #[serde(default)]
+    tweet_id: Option<String>,
+    user_key: String,
+    // milliseconds since the epoch
+    created_at: i64,
+    #[serde(default)]
+    retweet_count: Option<u32>,
+    #[serde(default, deserialize_with = "lenient_bool")]
+    retweeted: Option<bool>,
+    #[serde(default)]
+    favorite_count: Option<u32>,
+    text: String,
+}
+
+// the export writes booleans as TRUE/FALSE
+fn lenient_bool<'de, D: serde::Deserializer<'de>>(de: D) -> Result<Option<bool>, D::Error> {
+    let field = String::deserialize(de)?;
+    match field.to_ascii_lowercase().as_str() {
+        "" => Ok(None),
+        "true" => Ok(Some(true)),
+        "false" => Ok(Some(false)),
+        _ => Err(serde::de::Error::custom(format!(
+            "expected true or false, found `{}`",
+            field
+        ))),
+    }
+}
+
+impl TryFrom<TweetRow> for Tweet {
+    type Error = String;
+
+    fn try_from(row: TweetRow) -> Result<Self, Self::Error> {
+        if row.user_key.is_empty() {
+            return Err("empty user_key".to_string());
+        }
+        let created_at = Utc
+            .timestamp_millis_opt(row.created_at)
+            .single()
+            .filter(|ts| ts.timestamp() != 0)
+            .ok_or_else(|| format!("created_at out of range: {}", row.created_at))?;
+        let mut tweet = Tweet {
+            id: row.tweet_id.unwrap_or_default(),
+            user: handle_key(&row.user_key),
+            created_at,
+            retweet_count: row.retweet_count.unwrap_or(0),
+            retweeted: row.retweeted.unwrap_or(false),
+            favorite_count: row.favorite_count.unwrap_or(0),
+            text: row.text,
+            ..Tweet::default()
+        };
+        if tweet.id.is_empty() {
+            tweet.id = tweet.fallback_id();
+        }
+        Ok(tweet.with_entities())
+    }
+}
+
+#[derive(Debug)]
+pub struct RejectedRow {
+    // line the record starts on, 1-based
+    pub line: u64,
+    pub reason: String,
+}
+
+#[derive(Debug, Default)]
+pub struct LoadReport {
+    pub loaded: usize,
+    pub rejected: Vec<RejectedRow>,
+}
+
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "loaded {} tweets, rejected {} rows",
+            self.loaded,
+            self.rejected.len()
+        )?;
+        for row in self.rejected.iter().take(10) {
+            write!(f, "\n  line {}: {}", row.line, row.reason)?;
+        }
+        if self.rejected.len() > 10 {
+            write!(f, "\n  ... and {} more", self.rejected.len() - 10)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn load_tweets<P: AsRef<Path>>(path: P) -> anyhow::Result<(Vec<Tweet>, LoadReport)> {
+    let path = path.as_ref();
+    let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    read_tweets(file).with_context(|| format!("reading {}", path.display()))
+}
+
+pub fn read_tweets<R: Read>(reader: R) -> anyhow::Result<(Vec<Tweet>, LoadReport)> {
+    let mut csv = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(reader);
+    let mut tweets = Vec::new();
+    let mut report = LoadReport::default();
+
+    let mut record = StringRecord::new();
+    if !csv.read_record(&mut record)? {
+        return Ok((tweets, report));
+    }
+    // a header row names the columns; otherwise the first row is data
+    let headers = if record.iter().any(|field| field == "created_at") {
+        let headers = record.clone();
+        record.clear();
+        headers
+    } else {
+        StringRecord::from(DEFAULT_COLUMNS.to_vec())
+    };
+    for column in REQUIRED_COLUMNS {
+        if !headers.iter().any(|h| h == column) {
+            bail!("missing required column `{}`", column);
+        }
+    }
+
+    loop {
+        if !record.is_empty() {
+            let line = record.position().map(|p| p.line()).unwrap_or(0);
+            let parsed = record
+                .deserialize::<TweetRow>(Some(&headers))
+                .map_err(|e| match e.kind() {
+                    csv::ErrorKind::Deserialize { err, .. } => err.to_string(),
+                    _ => e.to_string(),
+                })
+                .and_then(Tweet::try_from);
+            match parsed {
+                Ok(tweet) => tweets.push(tweet),
+                Err(reason) => report.rejected.push(RejectedRow { line, reason }),
+            }
+        }
+
+        match csv.read_record(&mut record) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) if e.is_io_error() => return Err(e.into()),
+            Err(e) => {
+                let line = e.position().map(|p| p.line()).unwrap_or(0);
+                report.rejected.push(RejectedRow {
+                    line,
+                    reason: e.to_string(),
+                });
+                record.clear();
+            }
+        }
+    }
+
+    report.loaded = tweets.len();
+    Ok((tweets, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quoted_commas_and_newlines_survive() {
+        let data = "1,alice,1471272620000,,3,FALSE,5,\"RT @bob: hello, world\nsecond line\",100,,,,,,,\n\
+                    2,bob,1471272680000,,,,,plain,101,,,,,,,\n";
+        let (tweets, report) = read_tweets(data.as_bytes()).unwrap();
+
+        assert_eq!(report.loaded, 2);
+        assert!(report.rejected.is_empty());
+        assert_eq!(tweets[0].id, "100");
+        assert_eq!(tweets[0].user, "alice");
+        assert_eq!(tweets[0].text, "RT @bob: hello, world\nsecond line");
+        assert_eq!(tweets[0].retweet_from_user.as_deref(), Some("bob"));
+        assert_eq!(tweets[1].retweet_from_user, None);
+        assert_eq!(tweets[0].retweet_count, 3);
+        assert_eq!(tweets[0].favorite_count, 5);
+        assert!(!tweets[0].retweeted);
+        assert_eq!(tweets[1].retweet_count, 0);
+        assert_eq!(tweets[1].created_at.timestamp(), 1471272680);
+    }
+
+    #[test]
+    fn test_header_columns_in_any_order() {
+        let data = "text,created_at,user_key\nhi there,1471272620000,Carol\n";
+        let (tweets, _) = read_tweets(data.as_bytes()).unwrap();
+        assert_eq!(tweets.len(), 1);
+        // handles are keyed in lowercase, as the retweets naming them are
+        assert_eq!(tweets[0].user, "carol");
+        assert_eq!(tweets[0].text, "hi there");
+        // no tweet_id column, so the id is derived from the row
+        assert_eq!(tweets[0].id, tweets[0].fallback_id());
+    }
+
+    #[test]
+    fn test_bad_rows_are_reported_not_fatal() {
+        let data = "user_key,created_at,text,retweet_count\n\
+                    alice,1471272620000,ok,1\n\
+                    bob,,no timestamp,1\n\
+                    carol,1471272620000,bad count,many\n\
+                    dave,1471272620000\n";
+        let (tweets, report) = read_tweets(data.as_bytes()).unwrap();
+
+        assert_eq!(tweets.len(), 1);
+        let lines: Vec<u64> = report.rejected.iter().map(|r| r.line).collect();
+        assert_eq!(lines, vec![3, 4, 5]);
+    }
+
+    #[test]
+    fn test_missing_required_column_is_an_error() {
+        let data = "user_key,created_at\nalice,1471272620000\n";
+        assert!(read_tweets(data.as_bytes()).is_err());
+    }
+}
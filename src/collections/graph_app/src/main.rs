@@ -1,181 +1,448 @@
-use chrono::{DateTime, TimeZone, Utc};
-use neo4rs::*;
+use anyhow::{Context, bail};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use graph_app::analysis::activity::{ActivityConfig, Granularity, activity};
+use graph_app::analysis::metrics::{MetricsConfig, user_metrics, write_parquet};
+use graph_app::analysis::rapid_fire::{RapidFireConfig, rapid_fire};
+use graph_app::analysis::rings::{RingConfig, detect_rings};
+use graph_app::analysis::{ReportFormat, write_report};
+use graph_app::config::ConnectionSettings;
+use graph_app::export::{ExportFormat, ExportGraph};
+use graph_app::graph::{BulkConfig, InMemoryGraph, Neo4jGraph, TweetGraph};
+use graph_app::loader::load_tweets;
+use graph_app::tweet::Tweet;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::sync::Arc;
-
-#[derive(Debug)]
-struct Tweet {
-    user: String,
-    created_at: chrono::DateTime<Utc>,
-    retweet_count: u32,
-    retweeted: bool,
-    favorite_count: u32,
-    text: String,
-    retweet_from_user: String,
-}
-
-// the use of clone here seems unfavorable. Is there a better way?
-async fn insert_tweets(graph: Arc<Graph>, tweets: Vec<Tweet>) -> anyhow::Result<()> {
-    const BATCH_SIZE: usize = 500;
-
-    for batch in tweets.chunks(BATCH_SIZE) {
-        let mut tx = graph.start_txn().await?;
-        for tweet in batch {
-            tx.run(
-                query(
-                    "MERGE (u:User {user_id: $user_id})\
-                    MERGE (r:User {user_id: $retweet_from_user})\
-                    CREATE (t:Tweet {\
-                    text: $text,
-                    created_at: $created_at,
-                    retweet_count: $retweet_count,
-                    retweeted: $retweeted,
-                    favorite_count: $favorite_count,
-                    retweet_from_user: $retweet_from_user
-                    })
-                    MERGE (u)-[:TWEETED]->(t)
-                    MERGE (u)-[:RETWEETED]->(r)",
-                )
-                .param("user_id", tweet.user.clone())
-                .param("text", tweet.text.clone())
-                .param("created_at", tweet.created_at.timestamp())
-                .param("retweet_count", tweet.retweet_count)
-                .param("retweeted", tweet.retweeted)
-                .param("favorite_count", tweet.favorite_count)
-                .param("retweet_from_user", tweet.retweet_from_user.clone()),
-            )
-            .await?;
+use std::path::{Path, PathBuf};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+struct Cli {
+    /// Where the tweet graph lives. `memory` builds it from --data on every run.
+    #[arg(long, value_enum, default_value_t = Backend::Neo4j)]
+    backend: Backend,
+    /// Tweet export used by the in-memory backend.
+    #[arg(long, default_value = "data.csv")]
+    data: PathBuf,
+    #[command(flatten)]
+    connection: ConnectionArgs,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+enum Backend {
+    Neo4j,
+    Memory,
+}
+
+#[derive(Args, Debug)]
+struct ConnectionArgs {
+    #[arg(long, env = "NEO4J_URI")]
+    uri: Option<String>,
+    #[arg(long, env = "NEO4J_USER")]
+    user: Option<String>,
+    #[arg(long, env = "NEO4J_PASSWORD", hide_env_values = true)]
+    password: Option<String>,
+    /// TOML file with a [neo4j] table. Defaults to graph_app.toml if it exists.
+    #[arg(long, env = "GRAPH_APP_CONFIG")]
+    config: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    #[command(flatten)]
+    Graph(GraphCommand),
+    #[command(flatten)]
+    Offline(OfflineCommand),
+}
+
+// commands run against the selected backend
+#[derive(Subcommand, Debug)]
+enum GraphCommand {
+    /// Load a tweet CSV export into the graph.
+    Load {
+        csv: PathBuf,
+        /// Tweets sent to Neo4j per statement.
+        #[arg(long, default_value_t = 1000)]
+        batch_size: usize,
+        /// Batches inserted concurrently. More than one can deadlock in Neo4j
+        /// on shared users and hashtags; the driver retries, but may give up.
+        #[arg(long, default_value_t = 1)]
+        workers: usize,
+        /// Report what would be created or matched without writing anything.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Users who retweeted many distinct accounts.
+    Rings {
+        #[arg(long, default_value_t = 50)]
+        min_retweeted: usize,
+        /// Smallest group of mutually retweeting users to report (in-memory backend only).
+        #[arg(long, default_value_t = 2)]
+        min_ring_size: usize,
+    },
+    /// Update a graph loaded by an older version: remove the empty user that
+    /// original tweets were linked to as phantom retweets, lowercase handles,
+    /// and give tweets without a tweet_id the id of their row in the export.
+    Migrate {
+        /// The tweet CSV export the graph was loaded from.
+        csv: PathBuf,
+    },
+    /// Chains of users linked by retweets.
+    Paths {
+        #[arg(long, default_value_t = 3)]
+        min_hops: usize,
+        #[arg(long, default_value_t = 5)]
+        max_hops: usize,
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Users posting many tweets in quick succession.
+    RapidFire {
+        /// Consecutive tweets closer than this many seconds are rapid posts.
+        #[arg(long, default_value_t = 30)]
+        max_gap: i64,
+        /// Tweets in one window that make a burst (in-memory backend only).
+        #[arg(long, default_value_t = 5)]
+        burst_size: usize,
+        /// Burst window in seconds (in-memory backend only).
+        #[arg(long, default_value_t = 300)]
+        burst_window: i64,
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+}
+
+// commands built straight from the CSV in --data, whichever backend is selected
+#[derive(Subcommand, Debug)]
+enum OfflineCommand {
+    /// Write the graph built from --data to a .graphml, .gexf or .csv edge list.
+    Export {
+        output: PathBuf,
+        /// Leave out tweet nodes, keeping only the retweet network between users.
+        #[arg(long)]
+        users_only: bool,
+    },
+    /// Tweets per time bucket, overall and per user in --data, with spikes marked.
+    Activity {
+        #[arg(long, value_enum, default_value_t = Bucket::Hour)]
+        by: Bucket,
+        /// Buckets before each one that form its baseline.
+        #[arg(long, default_value_t = 24)]
+        window: usize,
+        /// Standard deviations above the baseline that make a spike.
+        #[arg(long, default_value_t = 3.0)]
+        threshold: f64,
+        /// Fewest tweets a spike can have.
+        #[arg(long, default_value_t = 5)]
+        min_tweets: u64,
+        #[arg(long)]
+        spikes_only: bool,
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+    /// PageRank, betweenness and community for each user in --data.
+    Metrics {
+        /// Write every user to a .csv, .json or .parquet file instead of
+        /// printing the top users.
+        #[arg(long)]
+        output: Option<PathBuf>,
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+enum Bucket {
+    Minute,
+    Hour,
+    Day,
+}
+
+impl From<Bucket> for Granularity {
+    fn from(bucket: Bucket) -> Self {
+        match bucket {
+            Bucket::Minute => Granularity::Minute,
+            Bucket::Hour => Granularity::Hour,
+            Bucket::Day => Granularity::Day,
         }
-        tx.commit().await?;
     }
-    Ok(())
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let uri = "neo4j+s://d7de04cc.databases.neo4j.io";
-    let user = "neo4j";
-    let pass = std::env::var("NEO4J_PASSWORD").unwrap();
-    let graph = Arc::new(Graph::new(uri, user, pass).await?);
-
-    // load tweets from file and insert them into the graph. This is a one time thing.
-    /*let tweets =
-        load_tweets("../data.csv");
-
-    let graph = Arc::new(Graph::new(uri, user, pass).await?);
-
-    let result = insert_tweets(graph.clone(), tweets).await;
-    println!("{:?}", result);*/
-
-    let retweet_rings_cyper = "MATCH (u1:User)-[:RETWEETED]->(u2:User) WITH u1,\
-                                     collect(u2.user_id) as retweeted_accounts, count(*) as n where n > 50 \
-                                     RETURN u1.user_id as user, n ORDER BY n DESC";
-    let mut result = graph.execute(query(retweet_rings_cyper)).await?;
-    while let Ok(Some(row)) = result.next().await {
-        let user_id: String = row.get("user").unwrap();
-        let n: i32 = row.get("n").unwrap();
-        println!("{}: {}", user_id, n);
-    }
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+enum OutputFormat {
+    Text,
+    Csv,
+    Json,
+}
 
-    let tight_group_cypher = "MATCH (u:User)-[:RETWEETED]->(v:User) \
-                                    WITH collect(DISTINCT u) + collect(DISTINCT v) AS users \
-                                    MATCH path = (a:User)-[:RETWEETED*3..5]-(b:User) return path limit 20";
-    let mut result = graph.execute(query(tight_group_cypher)).await?;
-    while let Ok(Some(row)) = result.next().await {
-        let path: Path = row.get("path").unwrap();
-        let mut user_ids = Vec::new();
-        for node in path.nodes() {
-            if node.labels().contains(&"User") {
-                if let Ok(user_id) = node.get::<String>("user_id") {
-                    user_ids.push(user_id);
-                }
-            }
+impl OutputFormat {
+    // `None` means plain text, printed by the caller
+    fn report_format(self) -> Option<ReportFormat> {
+        match self {
+            OutputFormat::Text => None,
+            OutputFormat::Csv => Some(ReportFormat::Csv),
+            OutputFormat::Json => Some(ReportFormat::Json),
         }
-        println!("{:?}", user_ids);
     }
+}
 
-    let rapid_fire = "MATCH (u:User)-[:TWEETED]->(t:Tweet) \
-                            WITH u, datetime({epochSeconds: t.created_at}) AS ts \
-                             ORDER BY u.user_id, ts WITH u, collect(ts) AS times \
-                             UNWIND range(0, size(times)-2) as i \
-                             WITH u, duration.inSeconds(times[i], times[i+1]) AS between, times[i] AS time1, times[i+1] AS time2 \
-                             WHERE between.seconds < 30 \
-                             RETURN u.user_id as user, count(*) AS rapid_posts \
-                             ORDER BY rapid_posts DESC LIMIT 10";
-    let mut rapid_fire_result = graph.execute(query(rapid_fire)).await?;
-    while let Ok(Some(row)) = rapid_fire_result.next().await {
-        let user_id: String = row.get("user").unwrap();
-        let rapid_posts: i32 = row.get("rapid_posts").unwrap();
-        println!("{}: {}", user_id, rapid_posts);
+impl ConnectionArgs {
+    fn settings(&self) -> anyhow::Result<ConnectionSettings> {
+        let (path, required) = match &self.config {
+            Some(path) => (path.as_path(), true),
+            None => (Path::new("graph_app.toml"), false),
+        };
+        let file = ConnectionSettings::from_file(path, required)?;
+        let flags = ConnectionSettings {
+            uri: self.uri.clone(),
+            user: self.user.clone(),
+            password: self.password.clone(),
+        };
+        Ok(flags.or(file))
     }
+}
 
+fn read_tweets(path: &Path) -> anyhow::Result<Vec<Tweet>> {
+    let (tweets, report) = load_tweets(path)?;
+    eprintln!("{}", report);
+    Ok(tweets)
+}
+
+fn export(data: &Path, output: &Path, users_only: bool) -> anyhow::Result<()> {
+    let format = ExportFormat::from_path(output)?;
+    let graph = ExportGraph::from_tweets(&read_tweets(data)?, !users_only);
+    let file = File::create(output).with_context(|| format!("creating {}", output.display()))?;
+    graph.write(format, file)?;
+    println!(
+        "wrote {} nodes and {} edges to {}",
+        graph.nodes.len(),
+        graph.edges.len(),
+        output.display()
+    );
     Ok(())
 }
 
-// a function which reads a CSV file and returns a vector of tweets
-fn load_tweets(filename: &str) -> Vec<Tweet> {
-    let mut tweets = Vec::new();
-    let csv = File::open(filename).unwrap();
-    let reader = BufReader::new(csv);
-    // for each line in the file
-    for line in reader.lines() {
-        let line = line.unwrap();
-        let parts = line.split(",").collect::<Vec<&str>>();
-        if parts.len() < 7 {
-            // skip this line
-            continue;
+fn metrics(data: &Path, output: Option<&Path>, limit: usize) -> anyhow::Result<()> {
+    let rows = user_metrics(&read_tweets(data)?, &MetricsConfig::default());
+    let Some(output) = output else {
+        for row in rows.iter().take(limit) {
+            println!(
+                "{}: pagerank {:.5}, betweenness {:.1}, community {} ({} users)",
+                row.user, row.pagerank, row.betweenness, row.community, row.community_size
+            );
         }
-        let tweet = Tweet {
-            user: parts[1].to_string(),
-            created_at: parse_timestamp(parts[2]),
-            retweet_count: safely_parse_number_sometimes_empty(parts[4]),
-            retweeted: safely_parse_bool_sometimes_empty(parts[5]),
-            favorite_count: safely_parse_number_sometimes_empty(parts[6]),
-            text: parts[7].to_string(),
-            retweet_from_user: extrapolate_rt(parts[7].to_string()),
-        };
-        tweets.push(tweet);
+        return Ok(());
+    };
+    let file = File::create(output).with_context(|| format!("creating {}", output.display()))?;
+    match output.extension().and_then(|ext| ext.to_str()) {
+        Some("csv") => write_report(&rows, ReportFormat::Csv, file)?,
+        Some("json") => write_report(&rows, ReportFormat::Json, file)?,
+        Some("parquet") => write_parquet(&rows, file)?,
+        _ => bail!(
+            "cannot tell the format of {}; use .csv, .json or .parquet",
+            output.display()
+        ),
     }
-
-    tweets
-        .into_iter()
-        .filter(|tweet| tweet.created_at.timestamp() != 0)
-        .collect()
+    println!(
+        "wrote metrics for {} users to {}",
+        rows.len(),
+        output.display()
+    );
+    Ok(())
 }
 
-fn safely_parse_bool_sometimes_empty(retweeted_field: &str) -> bool {
-    if retweeted_field.is_empty() {
-        false
-    } else {
-        retweeted_field.parse().unwrap_or(false)
+// `local` holds the tweets behind an in-memory graph, which lets the richer
+// native analyses run instead of the backend's queries
+async fn run<G: TweetGraph>(
+    graph: &mut G,
+    local: Option<&[Tweet]>,
+    command: GraphCommand,
+) -> anyhow::Result<()> {
+    match command {
+        GraphCommand::Load { csv, dry_run, .. } => {
+            let tweets = read_tweets(&csv)?;
+            if dry_run {
+                let plan = graph
+                    .plan_insert(&tweets)
+                    .await
+                    .context("planning insert")?;
+                println!("{}", plan);
+            } else {
+                let stats = graph
+                    .insert_tweets(&tweets)
+                    .await
+                    .context("inserting tweets")?;
+                println!("{}", stats);
+            }
+        }
+        GraphCommand::Rings {
+            min_retweeted,
+            min_ring_size,
+        } => match local {
+            Some(tweets) => {
+                let config = RingConfig {
+                    min_retweeted,
+                    min_ring_size,
+                };
+                let report = detect_rings(tweets, &config);
+                for user in report.heavy_retweeters {
+                    println!(
+                        "{}: {} accounts ({} retweets)",
+                        user.user, user.distinct_accounts, user.retweets
+                    );
+                }
+                for ring in report.rings {
+                    println!("ring of {}: {:?}", ring.members.len(), ring.members);
+                }
+            }
+            None => {
+                for ring in graph.retweet_rings(min_retweeted).await? {
+                    println!("{}: {}", ring.user, ring.count);
+                }
+            }
+        },
+        GraphCommand::Migrate { csv } => {
+            let stats = graph
+                .migrate(&read_tweets(&csv)?)
+                .await
+                .context("migrating graph")?;
+            println!("{}", stats);
+        }
+        GraphCommand::Paths {
+            min_hops,
+            max_hops,
+            limit,
+        } => {
+            for path in graph.retweet_paths(min_hops, max_hops, limit).await? {
+                println!("{:?}", path);
+            }
+        }
+        GraphCommand::RapidFire {
+            max_gap,
+            burst_size,
+            burst_window,
+            limit,
+            format,
+        } => match local {
+            Some(tweets) => {
+                let config = RapidFireConfig {
+                    max_gap_secs: max_gap,
+                    burst_size,
+                    burst_window_secs: burst_window,
+                    limit: Some(limit),
+                };
+                let users = rapid_fire(tweets, &config);
+                match format.report_format() {
+                    Some(format) => write_report(&users, format, std::io::stdout())?,
+                    None => {
+                        for user in users {
+                            println!(
+                                "{}: {} rapid posts, {} bursts",
+                                user.user, user.rapid_posts, user.bursts
+                            );
+                        }
+                    }
+                }
+            }
+            None => {
+                let users = graph.rapid_fire(max_gap, limit).await?;
+                match format.report_format() {
+                    Some(format) => write_report(&users, format, std::io::stdout())?,
+                    None => {
+                        for user in users {
+                            println!("{}: {}", user.user, user.count);
+                        }
+                    }
+                }
+            }
+        },
     }
+    Ok(())
 }
 
-fn safely_parse_number_sometimes_empty(count_field: &str) -> u32 {
-    if count_field.is_empty() {
-        0
-    } else {
-        count_field.parse().unwrap_or(0)
+// built straight from the CSV, whichever backend is selected
+fn run_offline(data: &Path, command: OfflineCommand) -> anyhow::Result<()> {
+    match command {
+        OfflineCommand::Export { output, users_only } => export(data, &output, users_only),
+        OfflineCommand::Metrics { output, limit } => metrics(data, output.as_deref(), limit),
+        OfflineCommand::Activity {
+            by,
+            window,
+            threshold,
+            min_tweets,
+            spikes_only,
+            format,
+        } => {
+            let config = ActivityConfig {
+                granularity: by.into(),
+                window,
+                threshold,
+                min_tweets,
+            };
+            let mut rows = activity(&read_tweets(data)?, &config);
+            if spikes_only {
+                rows.retain(|row| row.spike);
+            }
+            match format.report_format() {
+                Some(format) => write_report(&rows, format, std::io::stdout())?,
+                None => {
+                    for row in rows {
+                        println!(
+                            "{} {}: {} tweets, {} retweets, {} favorites (baseline {:.1}){}",
+                            row.start.to_rfc3339(),
+                            row.user.as_deref().unwrap_or("*"),
+                            row.tweets,
+                            row.retweets,
+                            row.favorites,
+                            row.baseline,
+                            if row.spike { " SPIKE" } else { "" }
+                        );
+                    }
+                }
+            }
+            Ok(())
+        }
     }
 }
 
-fn parse_timestamp(timestamp_string: &str) -> DateTime<Utc> {
-    let millis: i64 = timestamp_string.parse().unwrap_or(0);
-    let secs = millis / 1000;
-    let nanos = ((millis % 1000) * 1_000_000) as u32;
-    Utc.timestamp_opt(secs, nanos)
-        .single()
-        .unwrap_or_else(|| Utc.timestamp_opt(0, 0).single().unwrap())
-}
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let command = match cli.command {
+        Command::Graph(command) => command,
+        Command::Offline(command) => return run_offline(&cli.data, command),
+    };
 
-fn extrapolate_rt(text: String) -> String {
-    if text.contains("RT @") {
-        text.split("RT @").collect::<Vec<&str>>()[1]
-            .split(":")
-            .collect::<Vec<&str>>()[0]
-            .to_string()
-    } else {
-        "".to_string()
+    match cli.backend {
+        Backend::Memory => {
+            let mut graph = InMemoryGraph::new();
+            if let GraphCommand::Load { .. } = command {
+                return run(&mut graph, None, command).await;
+            }
+            let tweets = read_tweets(&cli.data)?;
+            graph.insert_tweets(&tweets).await?;
+            run(&mut graph, Some(&tweets), command).await
+        }
+        Backend::Neo4j => {
+            let connection = cli.connection.settings()?.resolve()?;
+            let mut graph =
+                Neo4jGraph::connect(&connection.uri, &connection.user, &connection.password)
+                    .await
+                    .with_context(|| format!("connecting to {}", connection.uri))?;
+            if let GraphCommand::Load {
+                batch_size,
+                workers,
+                ..
+            } = command
+            {
+                graph = graph.with_bulk_config(BulkConfig {
+                    batch_size,
+                    workers,
+                });
+            }
+            run(&mut graph, None, command).await
+        }
     }
 }
@@ -0,0 +1,297 @@
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Default)]
+pub struct Tweet {
+    /// Stable identifier that loads upsert by; see `Tweet::fallback_id`.
+    pub id: String,
+    /// The author's handle, lowercased like every handle; see `handle_key`.
+    pub user: String,
+    pub created_at: DateTime<Utc>,
+    pub retweet_count: u32,
+    pub retweeted: bool,
+    pub favorite_count: u32,
+    pub text: String,
+    /// The retweeted user; `None` for original tweets.
+    pub retweet_from_user: Option<String>,
+    /// Users @-mentioned in the text, other than the retweeted user.
+    pub mentions: Vec<String>,
+    /// Hashtags without the leading `#`, as written.
+    pub hashtags: Vec<String>,
+    pub urls: Vec<String>,
+    /// The user a reply is addressed to: the first @-mention opening the text.
+    pub reply_to: Option<String>,
+    /// The author of a tweet quoted by linking to it.
+    pub quote_of: Option<String>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct TextEntities {
+    pub retweet_of: Option<String>,
+    pub mentions: Vec<String>,
+    pub hashtags: Vec<String>,
+    pub urls: Vec<String>,
+    pub reply_to: Option<String>,
+    pub quote_of: Option<String>,
+}
+
+impl Tweet {
+    /// Identifier for rows exported without a tweet id, derived from the
+    /// author, time and text so that reloading the same row gives the same id.
+    pub fn fallback_id(&self) -> String {
+        // FNV-1a, which unlike std's hashers is fixed across Rust releases
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let created_at = self.created_at.timestamp_millis().to_le_bytes();
+        for part in [self.user.as_bytes(), &created_at, self.text.as_bytes()] {
+            for &byte in part.iter().chain(&[0xff]) {
+                hash ^= u64::from(byte);
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+        format!("h{:016x}", hash)
+    }
+
+    /// Fills the fields derived from `text`.
+    pub fn with_entities(mut self) -> Self {
+        let entities = analyze_text(&self.text);
+        self.retweet_from_user = entities.retweet_of;
+        self.mentions = entities.mentions;
+        self.hashtags = entities.hashtags;
+        self.urls = entities.urls;
+        self.reply_to = entities.reply_to;
+        self.quote_of = entities.quote_of;
+        self
+    }
+}
+
+/// The last row for each tweet id, in the order those rows appear. Loads
+/// upsert tweets by id, so a later row for a tweet replaces an earlier one.
+pub fn latest_by_id(tweets: &[Tweet]) -> impl Iterator<Item = &Tweet> {
+    let last: HashMap<&str, usize> = tweets
+        .iter()
+        .enumerate()
+        .map(|(i, tweet)| (tweet.id.as_str(), i))
+        .collect();
+    tweets
+        .iter()
+        .enumerate()
+        .filter(move |(i, tweet)| last[tweet.id.as_str()] == *i)
+        .map(|(_, tweet)| tweet)
+}
+
+// pulls the original author out of "RT @user: ..." style text
+pub fn extrapolate_rt(text: &str) -> Option<String> {
+    analyze_text(text).retweet_of
+}
+
+// twitter handles are ASCII letters, digits and underscores
+fn is_handle_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// The key a handle is stored under. Twitter ignores case in handles, so
+/// "@TEN_GOP" in a retweet and the author "ten_gop" are the same account.
+pub fn handle_key(handle: &str) -> String {
+    handle.to_ascii_lowercase()
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// the longest prefix of `s` made of chars matching `pred`
+fn take_while(s: &str, pred: fn(char) -> bool) -> &str {
+    let end = s.find(|c: char| !pred(c)).unwrap_or(s.len());
+    &s[..end]
+}
+
+/// Scans tweet text for retweet and reply markers, mentions, hashtags and URLs.
+/// Entities keep their first-seen order and are not repeated; handles are
+/// lowercased by `handle_key`.
+pub fn analyze_text(text: &str) -> TextEntities {
+    let mut entities = TextEntities::default();
+    let mut mentions = Vec::new();
+    let mut prev: Option<char> = None;
+    // end of the last URL found; its path and fragment hold no entities
+    let mut url_end = 0;
+
+    for (i, c) in text.char_indices() {
+        if i < url_end {
+            prev = Some(c);
+            continue;
+        }
+        let rest = &text[i + c.len_utf8()..];
+        // entities only start at a word boundary, so e-mail addresses are not
+        // mentions, and "/" is not one, so neither is "medium.com/@user"
+        let at_boundary = prev.is_none_or(|p| !is_tag_char(p) && p != '/');
+
+        if at_boundary && c == '@' {
+            let handle = take_while(rest, is_handle_char);
+            if !handle.is_empty() {
+                if is_retweet_marker(&text[..i]) && entities.retweet_of.is_none() {
+                    entities.retweet_of = Some(handle_key(handle));
+                } else {
+                    mentions.push(handle_key(handle));
+                }
+            }
+        } else if at_boundary && c == '#' {
+            let tag = take_while(rest, is_tag_char);
+            if tag.chars().any(|c| !c.is_ascii_digit()) {
+                entities.hashtags.push(tag.to_string());
+            }
+        } else if at_boundary
+            && (text[i..].starts_with("http://") || text[i..].starts_with("https://"))
+        {
+            let url = text[i..]
+                .split(char::is_whitespace)
+                .next()
+                .unwrap_or_default();
+            url_end = i + url.len();
+            // trailing punctuation and the ellipsis of truncated text are not part of the link
+            let url = url.trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '"', '\'', '…']);
+            if entities.quote_of.is_none() {
+                entities.quote_of = quoted_author(url);
+            }
+            entities.urls.push(url.to_string());
+        }
+        prev = Some(c);
+    }
+
+    // replies open with the mention of the user being replied to
+    entities.reply_to = text
+        .trim_start()
+        .strip_prefix('@')
+        .map(|rest| take_while(rest, is_handle_char))
+        .filter(|handle| !handle.is_empty())
+        .map(handle_key);
+
+    dedup_in_order(&mut mentions);
+    dedup_in_order(&mut entities.hashtags);
+    dedup_in_order(&mut entities.urls);
+    if let Some(source) = &entities.retweet_of {
+        mentions.retain(|m| m != source);
+    }
+    entities.mentions = mentions;
+    entities
+}
+
+// `before` ends in "RT " as a word of its own, not the end of "SMART "
+fn is_retweet_marker(before: &str) -> bool {
+    before
+        .strip_suffix("RT ")
+        .is_some_and(|rest| rest.is_empty() || rest.ends_with(char::is_whitespace))
+}
+
+// "https://twitter.com/<user>/status/<id>" links quote <user>'s tweet
+fn quoted_author(url: &str) -> Option<String> {
+    let path = url
+        .split_once("://")
+        .map(|(_, rest)| rest)
+        .unwrap_or(url)
+        .trim_start_matches("www.")
+        .trim_start_matches("mobile.");
+    let path = path
+        .strip_prefix("twitter.com/")
+        .or_else(|| path.strip_prefix("x.com/"))?;
+    let mut parts = path.split('/');
+    let user = parts.next()?;
+    if parts.next()? == "status" && !user.is_empty() && user.chars().all(is_handle_char) {
+        Some(handle_key(user))
+    } else {
+        None
+    }
+}
+
+fn dedup_in_order(items: &mut Vec<String>) {
+    let mut seen = std::collections::HashSet::new();
+    items.retain(|item| seen.insert(item.clone()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retweet_with_mentions_tags_and_links() {
+        let entities = analyze_text(
+            "RT @mc_derpin: #TheOlderWeGet the more pessimistic @Bob and @bob are https://t.co/zS3jHZJl8P.",
+        );
+        assert_eq!(entities.retweet_of.as_deref(), Some("mc_derpin"));
+        assert_eq!(entities.mentions, vec!["bob"]);
+        assert_eq!(entities.hashtags, vec!["TheOlderWeGet"]);
+        assert_eq!(entities.urls, vec!["https://t.co/zS3jHZJl8P"]);
+        assert_eq!(entities.reply_to, None);
+    }
+
+    #[test]
+    fn test_fallback_id_depends_only_on_author_time_and_text() {
+        let tweet = Tweet {
+            user: "alice".to_string(),
+            text: "hello".to_string(),
+            ..Tweet::default()
+        };
+        let counted = Tweet {
+            retweet_count: 7,
+            ..tweet.clone()
+        };
+        let other = Tweet {
+            text: "hello!".to_string(),
+            ..tweet.clone()
+        };
+        assert_eq!(tweet.fallback_id(), counted.fallback_id());
+        assert_ne!(tweet.fallback_id(), other.fallback_id());
+        assert_eq!(tweet.fallback_id(), "h39398e9f302d366c");
+    }
+
+    #[test]
+    fn test_retweet_handle_stops_at_punctuation() {
+        assert_eq!(
+            extrapolate_rt("RT @alice no colon here").as_deref(),
+            Some("alice")
+        );
+        assert_eq!(extrapolate_rt("no retweet @alice"), None);
+    }
+
+    #[test]
+    fn test_links_hold_no_mentions_or_hashtags() {
+        let entities =
+            analyze_text("read https://medium.com/@user/post#section and medium.com/@other #real");
+        assert!(entities.mentions.is_empty());
+        assert_eq!(entities.hashtags, vec!["real"]);
+        assert_eq!(entities.urls, vec!["https://medium.com/@user/post#section"]);
+    }
+
+    #[test]
+    fn test_retweet_marker_is_a_word_of_its_own() {
+        assert_eq!(
+            extrapolate_rt("RT @TEN_GOP: hi").as_deref(),
+            Some("ten_gop")
+        );
+        assert_eq!(
+            extrapolate_rt("so true RT @alice: hi").as_deref(),
+            Some("alice")
+        );
+        let smart = analyze_text("SMART @alice and ART @bob");
+        assert_eq!(smart.retweet_of, None);
+        assert_eq!(smart.mentions, vec!["alice", "bob"]);
+    }
+
+    #[test]
+    fn test_reply_target_is_leading_mention() {
+        let entities = analyze_text("@TuckerCarlson\n@JRubinBlogger #Bigot and #1 fan");
+        assert_eq!(entities.reply_to.as_deref(), Some("tuckercarlson"));
+        assert_eq!(entities.mentions, vec!["tuckercarlson", "jrubinblogger"]);
+        assert_eq!(entities.hashtags, vec!["Bigot"]);
+
+        let not_reply = analyze_text("thanks @alice");
+        assert_eq!(not_reply.reply_to, None);
+    }
+
+    #[test]
+    fn test_emails_are_not_mentions_and_quotes_are_found() {
+        let entities =
+            analyze_text("mail me@example.com, see https://twitter.com/jack/status/20 (wow)");
+        assert!(entities.mentions.is_empty());
+        assert_eq!(entities.quote_of.as_deref(), Some("jack"));
+    }
+}
@@ -1 +1,652 @@
+// A small, composable ETL pipeline.
+//
+// Records are pulled from a `Source`, pushed through zero or more `Transform`s
+// and handed to a `Sink`. Transforms are chained at compile time, so a pipeline
+// like `Pipeline::new(src).map(f).filter(g).run(&mut sink)` is a single loop
+// with no boxing or intermediate collections.
+use std::error::Error;
+use std::fmt;
+use std::fmt::Display;
+use std::io::{self, BufRead, Write};
 
+mod checkpoint;
+mod dead_letter;
+mod parallel;
+
+pub use checkpoint::Checkpoint;
+pub use dead_letter::{DeadLetter, DeadLetterFormat, DeadLetterReport};
+pub use parallel::ParallelPipeline;
+
+#[derive(Debug)]
+pub enum PipelineError {
+    Io(io::Error),
+    Transform(TransformError),
+}
+
+/// Why a transform failed. `reason` is the full message; `kind` names the sort
+/// of failure, so that failures which only differ in the value they quote are
+/// counted together.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransformError {
+    pub kind: String,
+    pub reason: String,
+}
+
+impl TransformError {
+    pub fn new(kind: impl Into<String>, reason: impl Display) -> Self {
+        TransformError {
+            kind: kind.into(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+/// Any other error's kind is the name of its type, without the module path:
+/// `ParseIntError` for `str::parse::<u32>`.
+impl<E: Display> From<E> for TransformError {
+    fn from(error: E) -> Self {
+        let name = std::any::type_name::<E>();
+        let name = name.split('<').next().unwrap_or(name);
+        TransformError::new(name.rsplit("::").next().unwrap_or(name), error)
+    }
+}
+
+impl Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PipelineError::Io(e) => write!(f, "i/o error: {}", e),
+            PipelineError::Transform(e) => write!(f, "transform failed: {}", e.reason),
+        }
+    }
+}
+
+impl Error for PipelineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PipelineError::Io(e) => Some(e),
+            PipelineError::Transform(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for PipelineError {
+    fn from(e: io::Error) -> Self {
+        PipelineError::Io(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, PipelineError>;
+
+// Where a dead-letter run sends failed records. The transform consumes each
+// record, so a copy is kept until it succeeds; only a failed record's copy is
+// rendered as text.
+struct Rejects<'a, R> {
+    dead_letter: &'a mut DeadLetter,
+    keep: fn(&R) -> R,
+    render: fn(&R) -> String,
+}
+
+/// Produces records one at a time. Returns `None` once the input is exhausted.
+pub trait Source {
+    type Record;
+
+    fn next_record(&mut self) -> Option<Result<Self::Record>>;
+
+    /// Discards up to `n` records, returning how many were actually skipped.
+    /// Used to resume from a checkpoint; sources that can seek should override it.
+    fn skip(&mut self, n: u64) -> Result<u64> {
+        let mut skipped = 0;
+        while skipped < n {
+            match self.next_record() {
+                Some(record) => {
+                    record?;
+                    skipped += 1;
+                }
+                None => break,
+            }
+        }
+        Ok(skipped)
+    }
+}
+
+/// Turns one record into zero or one output records.
+/// Returning `Ok(None)` drops the record from the rest of the pipeline.
+pub trait Transform<In> {
+    type Output;
+
+    fn apply(&mut self, record: In) -> Result<Option<Self::Output>>;
+}
+
+/// Consumes the records that make it out the end of the pipeline.
+pub trait Sink<R> {
+    fn write(&mut self, record: R) -> Result<()>;
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+// ---- sources ----
+
+/// Reads a `BufRead` line by line, without the trailing newline.
+pub struct LineSource<R> {
+    reader: R,
+}
+
+impl<R: BufRead> LineSource<R> {
+    pub fn new(reader: R) -> Self {
+        LineSource { reader }
+    }
+}
+
+impl<R: BufRead> Source for LineSource<R> {
+    type Record = String;
+
+    fn next_record(&mut self) -> Option<Result<String>> {
+        let mut line = String::new();
+        match self.reader.read_line(&mut line) {
+            Ok(0) => None,
+            Ok(_) => {
+                if line.ends_with('\n') {
+                    line.pop();
+                    if line.ends_with('\r') {
+                        line.pop();
+                    }
+                }
+                Some(Ok(line))
+            }
+            Err(e) => Some(Err(e.into())),
+        }
+    }
+}
+
+/// Wraps any iterator so it can feed a pipeline.
+pub struct IterSource<I> {
+    iter: I,
+}
+
+impl<I: Iterator> IterSource<I> {
+    pub fn new<T: IntoIterator<IntoIter = I>>(iter: T) -> Self {
+        IterSource {
+            iter: iter.into_iter(),
+        }
+    }
+}
+
+impl<I: Iterator> Source for IterSource<I> {
+    type Record = I::Item;
+
+    fn next_record(&mut self) -> Option<Result<I::Item>> {
+        self.iter.next().map(Ok)
+    }
+}
+
+// ---- transforms ----
+
+/// Passes records through unchanged. The starting point of every pipeline.
+#[derive(Clone)]
+pub struct Identity;
+
+impl<T> Transform<T> for Identity {
+    type Output = T;
+
+    fn apply(&mut self, record: T) -> Result<Option<T>> {
+        Ok(Some(record))
+    }
+}
+
+/// Runs `A`, then feeds whatever it produced into `B`.
+#[derive(Clone)]
+pub struct Chain<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<In, A, B> Transform<In> for Chain<A, B>
+where
+    A: Transform<In>,
+    B: Transform<A::Output>,
+{
+    type Output = B::Output;
+
+    fn apply(&mut self, record: In) -> Result<Option<B::Output>> {
+        match self.first.apply(record)? {
+            Some(mid) => self.second.apply(mid),
+            None => Ok(None),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Map<F>(pub F);
+
+impl<In, Out, F> Transform<In> for Map<F>
+where
+    F: FnMut(In) -> Out,
+{
+    type Output = Out;
+
+    fn apply(&mut self, record: In) -> Result<Option<Out>> {
+        Ok(Some((self.0)(record)))
+    }
+}
+
+#[derive(Clone)]
+pub struct Filter<F>(pub F);
+
+impl<T, F> Transform<T> for Filter<F>
+where
+    F: FnMut(&T) -> bool,
+{
+    type Output = T;
+
+    fn apply(&mut self, record: T) -> Result<Option<T>> {
+        if (self.0)(&record) {
+            Ok(Some(record))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// A fallible map: an `Err` aborts the run with `PipelineError::Transform`.
+/// Return a `TransformError` to pick the kind a failure is counted under.
+#[derive(Clone)]
+pub struct TryMap<F>(pub F);
+
+impl<In, Out, E, F> Transform<In> for TryMap<F>
+where
+    F: FnMut(In) -> std::result::Result<Out, E>,
+    E: Into<TransformError>,
+{
+    type Output = Out;
+
+    fn apply(&mut self, record: In) -> Result<Option<Out>> {
+        (self.0)(record)
+            .map(Some)
+            .map_err(|e| PipelineError::Transform(e.into()))
+    }
+}
+
+// ---- sinks ----
+
+/// Writes each record on its own line using its `Display` impl.
+pub struct LineSink<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> LineSink<W> {
+    pub fn new(writer: W) -> Self {
+        LineSink { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write, R: Display> Sink<R> for LineSink<W> {
+    fn write(&mut self, record: R) -> Result<()> {
+        writeln!(self.writer, "{}", record)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Collects records in memory. Handy for tests and small jobs.
+impl<R> Sink<R> for Vec<R> {
+    fn write(&mut self, record: R) -> Result<()> {
+        self.push(record);
+        Ok(())
+    }
+}
+
+// ---- the pipeline ----
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RunStats {
+    pub records_read: u64,
+    pub records_written: u64,
+    pub records_dropped: u64,
+    pub records_skipped: u64,
+    pub records_rejected: u64,
+}
+
+pub struct Pipeline<S, T> {
+    source: S,
+    transform: T,
+}
+
+impl<S: Source> Pipeline<S, Identity> {
+    pub fn new(source: S) -> Self {
+        Pipeline {
+            source,
+            transform: Identity,
+        }
+    }
+}
+
+impl<S, T> Pipeline<S, T>
+where
+    S: Source,
+    T: Transform<S::Record>,
+{
+    /// Appends a transform to the end of the chain.
+    pub fn then<U>(self, next: U) -> Pipeline<S, Chain<T, U>>
+    where
+        U: Transform<T::Output>,
+    {
+        Pipeline {
+            source: self.source,
+            transform: Chain {
+                first: self.transform,
+                second: next,
+            },
+        }
+    }
+
+    pub fn map<F, Out>(self, f: F) -> Pipeline<S, Chain<T, Map<F>>>
+    where
+        F: FnMut(T::Output) -> Out,
+    {
+        self.then(Map(f))
+    }
+
+    pub fn filter<F>(self, f: F) -> Pipeline<S, Chain<T, Filter<F>>>
+    where
+        F: FnMut(&T::Output) -> bool,
+    {
+        self.then(Filter(f))
+    }
+
+    pub fn try_map<F, Out, E>(self, f: F) -> Pipeline<S, Chain<T, TryMap<F>>>
+    where
+        F: FnMut(T::Output) -> std::result::Result<Out, E>,
+        E: Into<TransformError>,
+    {
+        self.then(TryMap(f))
+    }
+
+    /// Moves the source onto its own thread and runs the transform chain on
+    /// `workers` threads, with `capacity`-sized channels between them.
+    pub fn parallel(self, workers: usize, capacity: usize) -> ParallelPipeline<T::Output>
+    where
+        S: Send + 'static,
+        S::Record: Send + 'static,
+        T: Clone + Send + 'static,
+        T::Output: Send + 'static,
+    {
+        ParallelPipeline::from_source(self.source, capacity).stage(workers, self.transform)
+    }
+
+    /// Drains the source through the transforms into `sink`.
+    /// Stops at the first error; the sink is flushed on success.
+    pub fn run<K>(self, sink: &mut K) -> Result<RunStats>
+    where
+        K: Sink<T::Output>,
+    {
+        self.drive(sink, None, None)
+    }
+
+    /// Like `run`, but resumable. Skips the records committed by a previous
+    /// attempt, flushes the sink and saves progress to `checkpoint` after
+    /// every `batch_size` source records, and clears the checkpoint once the
+    /// source is exhausted.
+    ///
+    /// Records after the last checkpoint may be written twice if a run dies
+    /// mid-batch, so sinks that cannot tolerate that should upsert.
+    pub fn run_with_checkpoint<K>(
+        self,
+        sink: &mut K,
+        checkpoint: &Checkpoint,
+        batch_size: u64,
+    ) -> Result<RunStats>
+    where
+        K: Sink<T::Output>,
+    {
+        self.drive(sink, Some((checkpoint, batch_size.max(1))), None)
+    }
+
+    /// Like `run`, but a record whose transform fails is written to
+    /// `dead_letter` with its position in the source and the error, and the
+    /// run carries on. I/O errors still abort.
+    ///
+    /// Each record is cloned before the transform takes it, and rendered with
+    /// `Display` only if the transform fails. There is no checkpointed form: a
+    /// resumed run would start a fresh dead-letter file and could reject the
+    /// records after the last checkpoint twice. `ParallelPipeline` has no
+    /// dead-letter support either.
+    pub fn run_with_dead_letter<K>(
+        self,
+        sink: &mut K,
+        dead_letter: &mut DeadLetter,
+    ) -> Result<RunStats>
+    where
+        K: Sink<T::Output>,
+        S::Record: Clone + Display,
+    {
+        let rejects = Rejects {
+            dead_letter,
+            keep: S::Record::clone,
+            render: S::Record::to_string,
+        };
+        self.drive(sink, None, Some(rejects))
+    }
+
+    fn drive<K>(
+        mut self,
+        sink: &mut K,
+        checkpoint: Option<(&Checkpoint, u64)>,
+        mut rejects: Option<Rejects<S::Record>>,
+    ) -> Result<RunStats>
+    where
+        K: Sink<T::Output>,
+    {
+        let mut stats = RunStats::default();
+        if let Some((checkpoint, _)) = checkpoint {
+            let offset = checkpoint.load()?;
+            stats.records_skipped = self.source.skip(offset)?;
+        }
+
+        while let Some(record) = self.source.next_record() {
+            stats.records_read += 1;
+            let record = record?;
+            let kept = rejects.as_ref().map(|rejects| (rejects.keep)(&record));
+            match self.transform.apply(record) {
+                Ok(Some(out)) => {
+                    sink.write(out)?;
+                    stats.records_written += 1;
+                }
+                Ok(None) => stats.records_dropped += 1,
+                Err(PipelineError::Transform(error)) => match (&mut rejects, kept) {
+                    (Some(rejects), Some(kept)) => {
+                        let line = stats.records_skipped + stats.records_read;
+                        let raw = (rejects.render)(&kept);
+                        rejects.dead_letter.reject(line, &raw, &error)?;
+                        stats.records_rejected += 1;
+                    }
+                    _ => return Err(PipelineError::Transform(error)),
+                },
+                Err(e) => return Err(e),
+            }
+            if let Some((checkpoint, batch_size)) = checkpoint
+                && stats.records_read % batch_size == 0
+            {
+                sink.flush()?;
+                checkpoint.save(stats.records_skipped + stats.records_read)?;
+            }
+        }
+        sink.flush()?;
+        if let Some((checkpoint, _)) = checkpoint {
+            checkpoint.clear()?;
+        }
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_lines_through_chained_transforms() {
+        let input = Cursor::new("the quick\r\nbrown fox\n\njumps\n");
+        let mut out = LineSink::new(Vec::new());
+        let stats = Pipeline::new(LineSource::new(input))
+            .filter(|line: &String| !line.is_empty())
+            .map(|line| line.to_uppercase())
+            .run(&mut out)
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(out.into_inner()).unwrap(),
+            "THE QUICK\nBROWN FOX\nJUMPS\n"
+        );
+        assert_eq!(
+            stats,
+            RunStats {
+                records_read: 4,
+                records_written: 3,
+                records_dropped: 1,
+                records_skipped: 0,
+                records_rejected: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_try_map_error_stops_run() {
+        let mut out: Vec<u32> = Vec::new();
+        let result = Pipeline::new(IterSource::new(["1", "2", "x", "4"]))
+            .try_map(|s: &str| s.parse::<u32>())
+            .run(&mut out);
+
+        assert!(matches!(result, Err(PipelineError::Transform(_))));
+        assert_eq!(out, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_resume_from_checkpoint() {
+        struct FailAt {
+            written: Vec<u32>,
+            fail_at: Option<u32>,
+        }
+        impl Sink<u32> for FailAt {
+            fn write(&mut self, record: u32) -> Result<()> {
+                if Some(record) == self.fail_at {
+                    return Err(PipelineError::Transform(
+                        "simulated crash".to_string().into(),
+                    ));
+                }
+                self.written.push(record);
+                Ok(())
+            }
+        }
+
+        let path = std::env::temp_dir().join(format!("pipeline-ckpt-{}", std::process::id()));
+        let checkpoint = Checkpoint::new(&path);
+        checkpoint.clear().unwrap();
+
+        let mut first = FailAt {
+            written: Vec::new(),
+            fail_at: Some(8),
+        };
+        let result = Pipeline::new(IterSource::new(1..=10u32)).run_with_checkpoint(
+            &mut first,
+            &checkpoint,
+            3,
+        );
+        assert!(result.is_err());
+        assert_eq!(checkpoint.load().unwrap(), 6);
+
+        let mut second = FailAt {
+            written: Vec::new(),
+            fail_at: None,
+        };
+        let stats = Pipeline::new(IterSource::new(1..=10u32))
+            .run_with_checkpoint(&mut second, &checkpoint, 3)
+            .unwrap();
+        assert_eq!(second.written, vec![7, 8, 9, 10]);
+        assert_eq!(stats.records_skipped, 6);
+        assert_eq!(stats.records_read, 4);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_failed_records_go_to_dead_letter() {
+        let path = std::env::temp_dir().join(format!("pipeline-dlq-{}.csv", std::process::id()));
+        let mut dead_letter = DeadLetter::create(&path).unwrap();
+        let mut out: Vec<u32> = Vec::new();
+        let input = Cursor::new("1\n2\nthree\n4\n-5\n");
+
+        let stats = Pipeline::new(LineSource::new(input))
+            .try_map(|s: String| s.parse::<u32>())
+            .run_with_dead_letter(&mut out, &mut dead_letter)
+            .unwrap();
+        let report = dead_letter.finish().unwrap();
+
+        assert_eq!(out, vec![1, 2, 4]);
+        assert_eq!(stats.records_rejected, 2);
+        assert_eq!(report.total, 2);
+        assert_eq!(report.by_kind["ParseIntError"], 2);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "line,kind,reason,record\n\
+             3,ParseIntError,invalid digit found in string,three\n\
+             5,ParseIntError,invalid digit found in string,-5\n"
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_dead_letter_json_lines_read_back() {
+        let path = std::env::temp_dir().join(format!("pipeline-dlq-{}.jsonl", std::process::id()));
+        let mut dead_letter = DeadLetter::create(&path).unwrap();
+        let mut out: Vec<u32> = Vec::new();
+        let input = ["7", "say \"hi\"\tthen, \\ leave", "0"];
+
+        Pipeline::new(IterSource::new(input))
+            .try_map(|s: &str| match s.parse::<u32>() {
+                Ok(0) => Err(TransformError::new("zero", format!("zero at {:?}", s))),
+                Ok(n) => Ok(n),
+                Err(e) => Err(e.into()),
+            })
+            .run_with_dead_letter(&mut out, &mut dead_letter)
+            .unwrap();
+        let report = dead_letter.finish().unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        let rows: Vec<serde_json::Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                serde_json::json!({
+                    "line": 2,
+                    "kind": "ParseIntError",
+                    "reason": "invalid digit found in string",
+                    "record": "say \"hi\"\tthen, \\ leave",
+                }),
+                serde_json::json!({
+                    "line": 3,
+                    "kind": "zero",
+                    "reason": "zero at \"0\"",
+                    "record": "0",
+                }),
+            ]
+        );
+        // one record per line, whatever the record holds
+        assert_eq!(text.lines().count(), 2);
+        assert!(text.contains(r#""say \"hi\"\tthen, \\ leave""#));
+        assert_eq!(out, vec![7]);
+        assert_eq!(report.by_kind.len(), 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
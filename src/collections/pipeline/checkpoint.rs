@@ -0,0 +1,57 @@
+// Progress tracking for resumable pipeline runs.
+//
+// A checkpoint is a small text file holding the number of source records that
+// have been fully written and flushed to the sink. It is rewritten after every
+// committed batch, so a crashed run can skip straight past the work it already
+// did, and removed once a run completes.
+use super::{PipelineError, Result};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub struct Checkpoint {
+    path: PathBuf,
+}
+
+impl Checkpoint {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Checkpoint {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the last committed source offset, or 0 if there is no checkpoint.
+    pub fn load(&self) -> Result<u64> {
+        match fs::read_to_string(&self.path) {
+            Ok(contents) => contents.trim().parse().map_err(|_| {
+                PipelineError::Io(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("corrupt checkpoint file {}", self.path.display()),
+                ))
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Records `offset`. Writes to a temporary file and renames it over the
+    /// old checkpoint so a crash mid-write never leaves a truncated file.
+    pub fn save(&self, offset: u64) -> Result<()> {
+        let mut tmp = self.path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, format!("{}\n", offset))?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    pub fn clear(&self) -> Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,120 @@
+// Dead-letter output for records a pipeline could not process.
+//
+// Instead of aborting the run (or silently dropping the record), a rejected
+// record is written to a side file along with its 1-based position in the
+// source and why it failed. Counts are kept per kind of failure rather than per
+// message, since messages often quote the bad value, so the run can finish
+// with a short summary of what went wrong.
+use super::{Result, TransformError};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeadLetterFormat {
+    Csv,
+    JsonLines,
+}
+
+impl DeadLetterFormat {
+    /// `.jsonl`, `.ndjson` and `.json` paths get JSON lines, anything else CSV.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("jsonl" | "ndjson" | "json") => DeadLetterFormat::JsonLines,
+            _ => DeadLetterFormat::Csv,
+        }
+    }
+}
+
+enum Output {
+    Csv(Box<csv::Writer<Box<dyn Write + Send>>>),
+    JsonLines(Box<dyn Write + Send>),
+}
+
+pub struct DeadLetter {
+    output: Output,
+    counts: BTreeMap<String, u64>,
+    total: u64,
+}
+
+impl DeadLetter {
+    pub fn new<W: Write + Send + 'static>(writer: W, format: DeadLetterFormat) -> Result<Self> {
+        let writer: Box<dyn Write + Send> = Box::new(writer);
+        let output = match format {
+            DeadLetterFormat::Csv => {
+                let mut csv = csv::Writer::from_writer(writer);
+                csv.write_record(["line", "kind", "reason", "record"])
+                    .map_err(std::io::Error::from)?;
+                Output::Csv(Box::new(csv))
+            }
+            DeadLetterFormat::JsonLines => Output::JsonLines(writer),
+        };
+        Ok(DeadLetter {
+            output,
+            counts: BTreeMap::new(),
+            total: 0,
+        })
+    }
+
+    /// Creates (or truncates) `path`, picking the format from its extension.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let file = BufWriter::new(File::create(path)?);
+        DeadLetter::new(file, DeadLetterFormat::from_path(path))
+    }
+
+    pub fn reject(&mut self, line: u64, record: &str, error: &TransformError) -> Result<()> {
+        let TransformError { kind, reason } = error;
+        match &mut self.output {
+            Output::Csv(csv) => csv
+                .write_record([line.to_string().as_str(), kind, reason, record])
+                .map_err(std::io::Error::from)?,
+            Output::JsonLines(writer) => {
+                let json = serde_json::json!({
+                    "line": line,
+                    "kind": kind,
+                    "reason": reason,
+                    "record": record,
+                });
+                writeln!(writer, "{}", json)?;
+            }
+        }
+        *self.counts.entry(kind.clone()).or_insert(0) += 1;
+        self.total += 1;
+        Ok(())
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Flushes the file and returns the counts per kind of failure.
+    pub fn finish(mut self) -> Result<DeadLetterReport> {
+        match &mut self.output {
+            Output::Csv(csv) => csv.flush()?,
+            Output::JsonLines(writer) => writer.flush()?,
+        }
+        Ok(DeadLetterReport {
+            total: self.total,
+            by_kind: self.counts,
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DeadLetterReport {
+    pub total: u64,
+    pub by_kind: BTreeMap<String, u64>,
+}
+
+impl fmt::Display for DeadLetterReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "rejected records: {}", self.total)?;
+        for (kind, count) in &self.by_kind {
+            writeln!(f, "  {:>8}  {}", count, kind)?;
+        }
+        Ok(())
+    }
+}
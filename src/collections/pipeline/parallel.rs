@@ -0,0 +1,211 @@
+// Threaded pipeline stages connected by bounded crossbeam channels.
+//
+// The source runs on its own thread, each stage runs on one or more worker
+// threads, and the sink drains the last channel on the calling thread. Every
+// channel is bounded, so a slow sink fills the buffers and then blocks the
+// stages and the source behind it rather than letting them run ahead.
+//
+// With more than one worker in a stage, records may reach the sink out of order.
+// There is no checkpoint or dead-letter support here: a failed transform ends
+// the run, as it does in `Pipeline::run`.
+use super::{PipelineError, Result, RunStats, Sink, Source, Transform};
+use crossbeam::channel::{Receiver, bounded};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
+
+#[derive(Default)]
+struct Counters {
+    read: AtomicU64,
+    dropped: AtomicU64,
+}
+
+pub struct ParallelPipeline<R> {
+    rx: Receiver<Result<R>>,
+    capacity: usize,
+    handles: Vec<JoinHandle<()>>,
+    counters: Arc<Counters>,
+}
+
+impl<R: Send + 'static> ParallelPipeline<R> {
+    /// Starts pulling from `source` on a background thread. `capacity` bounds
+    /// every channel in the pipeline, including the ones added by `stage`.
+    pub fn from_source<S>(mut source: S, capacity: usize) -> Self
+    where
+        S: Source<Record = R> + Send + 'static,
+    {
+        let (tx, rx) = bounded(capacity);
+        let counters = Arc::new(Counters::default());
+        let source_counters = Arc::clone(&counters);
+
+        let handle = thread::spawn(move || {
+            while let Some(record) = source.next_record() {
+                source_counters.read.fetch_add(1, Ordering::Relaxed);
+                let failed = record.is_err();
+                // a send error means everything downstream has hung up
+                if tx.send(record).is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        ParallelPipeline {
+            rx,
+            capacity,
+            handles: vec![handle],
+            counters,
+        }
+    }
+
+    /// Adds a stage of `workers` threads, each running its own clone of `transform`.
+    pub fn stage<T>(self, workers: usize, transform: T) -> ParallelPipeline<T::Output>
+    where
+        T: Transform<R> + Clone + Send + 'static,
+        T::Output: Send + 'static,
+    {
+        let (tx, rx) = bounded(self.capacity);
+        let mut handles = self.handles;
+
+        for _ in 0..workers.max(1) {
+            let upstream = self.rx.clone();
+            let tx = tx.clone();
+            let counters = Arc::clone(&self.counters);
+            let mut transform = transform.clone();
+
+            handles.push(thread::spawn(move || {
+                for record in upstream.iter() {
+                    let out = match record.and_then(|r| transform.apply(r)) {
+                        Ok(Some(out)) => Ok(out),
+                        Ok(None) => {
+                            counters.dropped.fetch_add(1, Ordering::Relaxed);
+                            continue;
+                        }
+                        Err(e) => Err(e),
+                    };
+                    let failed = out.is_err();
+                    if tx.send(out).is_err() || failed {
+                        break;
+                    }
+                }
+            }));
+        }
+
+        ParallelPipeline {
+            rx,
+            capacity: self.capacity,
+            handles,
+            counters: self.counters,
+        }
+    }
+
+    /// Drains the last stage into `sink` on the calling thread and waits for
+    /// every worker to finish. Stops at the first error from any stage.
+    pub fn run<K>(self, sink: &mut K) -> Result<RunStats>
+    where
+        K: Sink<R>,
+    {
+        let mut written = 0;
+        let mut outcome = Ok(());
+        for record in self.rx.iter() {
+            match record.and_then(|r| sink.write(r)) {
+                Ok(()) => written += 1,
+                Err(e) => {
+                    outcome = Err(e);
+                    break;
+                }
+            }
+        }
+        // hanging up lets any blocked upstream sender fail and exit
+        drop(self.rx);
+
+        let mut panicked = false;
+        for handle in self.handles {
+            panicked |= handle.join().is_err();
+        }
+        outcome?;
+        if panicked {
+            return Err(PipelineError::Transform(
+                "a pipeline worker thread panicked".to_string().into(),
+            ));
+        }
+        sink.flush()?;
+
+        Ok(RunStats {
+            records_read: self.counters.read.load(Ordering::Relaxed),
+            records_written: written,
+            records_dropped: self.counters.dropped.load(Ordering::Relaxed),
+            records_skipped: 0,
+            records_rejected: 0,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collections::pipeline::{Filter, IterSource, Map, Pipeline, TryMap};
+    use std::time::Duration;
+
+    #[test]
+    fn test_stages_with_several_workers() {
+        let mut out: Vec<u64> = Vec::new();
+        let stats = ParallelPipeline::from_source(IterSource::new(0..1000u64), 8)
+            .stage(4, Map(|n: u64| n * 2))
+            .stage(2, Filter(|n: &u64| n.is_multiple_of(3)))
+            .run(&mut out)
+            .unwrap();
+
+        out.sort();
+        let expected: Vec<u64> = (0..1000u64)
+            .map(|n| n * 2)
+            .filter(|n| n.is_multiple_of(3))
+            .collect();
+        assert_eq!(out, expected);
+        assert_eq!(stats.records_read, 1000);
+        assert_eq!(stats.records_written, expected.len() as u64);
+        assert_eq!(stats.records_dropped, 1000 - expected.len() as u64);
+    }
+
+    #[test]
+    fn test_slow_sink_throttles_source() {
+        struct SlowSink {
+            produced: Arc<AtomicU64>,
+            written: u64,
+            max_ahead: u64,
+        }
+        impl Sink<u64> for SlowSink {
+            fn write(&mut self, _: u64) -> Result<()> {
+                thread::sleep(Duration::from_millis(1));
+                self.written += 1;
+                let ahead = self.produced.load(Ordering::SeqCst) - self.written;
+                self.max_ahead = self.max_ahead.max(ahead);
+                Ok(())
+            }
+        }
+
+        let produced = Arc::new(AtomicU64::new(0));
+        let counter = Arc::clone(&produced);
+        let source = IterSource::new((0..200u64).inspect(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }));
+        let mut sink = SlowSink {
+            produced,
+            written: 0,
+            max_ahead: 0,
+        };
+        Pipeline::new(source).parallel(1, 2).run(&mut sink).unwrap();
+
+        assert_eq!(sink.written, 200);
+        // two channels of 2, one record inside the worker, one blocked in the source
+        assert!(sink.max_ahead <= 6, "source ran {} ahead", sink.max_ahead);
+    }
+
+    #[test]
+    fn test_stage_error_is_returned() {
+        let mut out: Vec<u32> = Vec::new();
+        let result = ParallelPipeline::from_source(IterSource::new(["1", "x", "3"]), 1)
+            .stage(1, TryMap(|s: &str| s.parse::<u32>()))
+            .run(&mut out);
+        assert!(matches!(result, Err(PipelineError::Transform(_))));
+    }
+}
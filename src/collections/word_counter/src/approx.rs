@@ -0,0 +1,488 @@
+// Approximate word counts in bounded memory.
+//
+// Two structures see every word. A Count-Min Sketch estimates the count of any
+// word: it never underestimates, and with probability `1 - delta` overestimates
+// by at most `epsilon` times the number of words counted. It takes
+// `e / epsilon * ln(1 / delta)` counters whatever the input. A Space-Saving
+// summary keeps `capacity` candidate heavy hitters. When it is full, a new word
+// takes over the least counted slot and inherits its count as possible error,
+// so a slot's count never underestimates either and `count - error` never
+// overestimates. Any word occurring more than `total / capacity` times is
+// guaranteed a slot.
+//
+// The reported count of a word is the lower of its two estimates, and its
+// error the most that count can be over: the gap down to the Space-Saving
+// lower bound. Both structures merge, so files can be summarized in parallel.
+use crate::count::{WordCounts, for_each_word};
+use crate::ngram::NgramWindow;
+use crate::normalize::Normalizer;
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ApproxConfig {
+    /// Overestimate allowed by the sketch, as a fraction of all words counted.
+    pub epsilon: f64,
+    /// Chance of a sketch estimate exceeding that bound.
+    pub delta: f64,
+    /// Words tracked as top candidates.
+    pub capacity: usize,
+}
+
+impl Default for ApproxConfig {
+    fn default() -> Self {
+        ApproxConfig {
+            epsilon: 1e-4,
+            delta: 0.01,
+            capacity: 10_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CountMinSketch {
+    width: usize,
+    depth: usize,
+    // `depth` rows of `width` counters
+    counters: Vec<u64>,
+}
+
+impl CountMinSketch {
+    pub fn new(epsilon: f64, delta: f64) -> Self {
+        assert!(epsilon > 0.0 && delta > 0.0 && delta < 1.0);
+        let width = (std::f64::consts::E / epsilon).ceil() as usize;
+        let depth = (1.0 / delta).ln().ceil().max(1.0) as usize;
+        CountMinSketch {
+            width,
+            depth,
+            counters: vec![0; width * depth],
+        }
+    }
+
+    // one counter per row, from two halves of a single hash
+    fn cells(&self, word: &str) -> impl Iterator<Item = usize> + use<> {
+        // `DefaultHasher::new` is unkeyed, so every sketch hashes alike and
+        // sketches can be merged
+        let mut hasher = DefaultHasher::new();
+        word.hash(&mut hasher);
+        let hash = hasher.finish();
+        let (h1, h2) = (hash as u32 as u64, (hash >> 32) | 1);
+        let width = self.width as u64;
+        (0..self.depth as u64)
+            .map(move |row| (row * width + h1.wrapping_add(row.wrapping_mul(h2)) % width) as usize)
+    }
+
+    pub fn add(&mut self, word: &str) {
+        for cell in self.cells(word) {
+            self.counters[cell] += 1;
+        }
+    }
+
+    pub fn estimate(&self, word: &str) -> u64 {
+        self.cells(word)
+            .map(|cell| self.counters[cell])
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub fn merge(&mut self, other: &CountMinSketch) {
+        assert_eq!(
+            (self.width, self.depth),
+            (other.width, other.depth),
+            "merging sketches of different sizes"
+        );
+        for (mine, theirs) in self.counters.iter_mut().zip(&other.counters) {
+            *mine += theirs;
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Slot {
+    word: String,
+    count: u64,
+    error: u64,
+}
+
+/// Space-Saving top-K summary. Slots sit in a binary min-heap on count, so the
+/// slot to give up is always at the root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpaceSaving {
+    capacity: usize,
+    heap: Vec<Slot>,
+    // word -> its slot's position in `heap`
+    index: HashMap<String, usize>,
+}
+
+impl SpaceSaving {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "space saving needs at least one slot");
+        SpaceSaving {
+            capacity,
+            heap: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, word: &str) {
+        if let Some(&i) = self.index.get(word) {
+            self.heap[i].count += 1;
+            self.sift_down(i);
+        } else if self.heap.len() < self.capacity {
+            self.heap.push(Slot {
+                word: word.to_string(),
+                count: 1,
+                error: 0,
+            });
+            self.index.insert(word.to_string(), self.heap.len() - 1);
+            self.sift_up(self.heap.len() - 1);
+        } else {
+            let min = self.heap[0].count;
+            let evicted = std::mem::replace(
+                &mut self.heap[0],
+                Slot {
+                    word: word.to_string(),
+                    count: min + 1,
+                    error: min,
+                },
+            );
+            self.index.remove(&evicted.word);
+            self.index.insert(word.to_string(), 0);
+            self.sift_down(0);
+        }
+    }
+
+    // what an untracked word may have been counted, at most
+    fn floor(&self) -> u64 {
+        if self.heap.len() < self.capacity {
+            0
+        } else {
+            self.heap[0].count
+        }
+    }
+
+    /// Merges as in Agarwal et al., "Mergeable Summaries": a word missing from
+    /// one side is taken to have that side's floor, then the `capacity`
+    /// largest are kept.
+    pub fn merge(&mut self, other: &SpaceSaving) {
+        let (my_floor, their_floor) = (self.floor(), other.floor());
+        let mut slots: Vec<Slot> = Vec::with_capacity(self.heap.len() + other.heap.len());
+        for slot in &self.heap {
+            let (count, error) = match other.index.get(&slot.word) {
+                Some(&i) => (other.heap[i].count, other.heap[i].error),
+                None => (their_floor, their_floor),
+            };
+            slots.push(Slot {
+                word: slot.word.clone(),
+                count: slot.count + count,
+                error: slot.error + error,
+            });
+        }
+        for slot in &other.heap {
+            if !self.index.contains_key(&slot.word) {
+                slots.push(Slot {
+                    word: slot.word.clone(),
+                    count: slot.count + my_floor,
+                    error: slot.error + my_floor,
+                });
+            }
+        }
+        slots.sort_unstable_by(|a, b| b.count.cmp(&a.count).then_with(|| a.word.cmp(&b.word)));
+        slots.truncate(self.capacity);
+        // ascending order is already a valid min-heap
+        slots.reverse();
+        self.index = slots
+            .iter()
+            .enumerate()
+            .map(|(i, slot)| (slot.word.clone(), i))
+            .collect();
+        self.heap = slots;
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.heap.swap(a, b);
+        for i in [a, b] {
+            if let Some(position) = self.index.get_mut(&self.heap[i].word) {
+                *position = i;
+            }
+        }
+    }
+
+    fn sift_up(&mut self, mut i: usize) {
+        while i > 0 {
+            let parent = (i - 1) / 2;
+            if self.heap[parent].count <= self.heap[i].count {
+                break;
+            }
+            self.swap(i, parent);
+            i = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut i: usize) {
+        loop {
+            let mut smallest = i;
+            for child in [2 * i + 1, 2 * i + 2] {
+                if child < self.heap.len() && self.heap[child].count < self.heap[smallest].count {
+                    smallest = child;
+                }
+            }
+            if smallest == i {
+                break;
+            }
+            self.swap(i, smallest);
+            i = smallest;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Estimate<'a> {
+    pub word: &'a str,
+    /// Never below the true count.
+    pub count: u64,
+    /// The most `count` can be above the true count.
+    pub error: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApproxCounts {
+    sketch: CountMinSketch,
+    top: SpaceSaving,
+    total: u64,
+}
+
+impl ApproxCounts {
+    pub fn new(config: &ApproxConfig) -> Self {
+        ApproxCounts {
+            sketch: CountMinSketch::new(config.epsilon, config.delta),
+            top: SpaceSaving::new(config.capacity),
+            total: 0,
+        }
+    }
+
+    pub fn add(&mut self, word: &str) {
+        self.total += 1;
+        self.sketch.add(word);
+        self.top.add(word);
+    }
+
+    /// Adds the n-grams of `reader`, single words when `n` is 1. Reading many
+    /// inputs into one summary keeps memory fixed however many there are.
+    pub fn add_reader<R: BufRead>(
+        &mut self,
+        reader: R,
+        normalizer: &Normalizer,
+        n: usize,
+    ) -> io::Result<()> {
+        let mut window = NgramWindow::new(n);
+        for_each_word(reader, normalizer, |word| {
+            if let Some(ngram) = window.push(word) {
+                self.add(ngram);
+            }
+        })
+    }
+
+    pub fn merge(&mut self, other: &ApproxCounts) {
+        self.total += other.total;
+        self.sketch.merge(&other.sketch);
+        self.top.merge(&other.top);
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Candidate heavy hitters by descending estimated count, ties in
+    /// alphabetical order. `limit` keeps only the first `limit`.
+    pub fn top(&self, limit: Option<usize>) -> Vec<Estimate<'_>> {
+        let mut estimates: Vec<Estimate> = self
+            .top
+            .heap
+            .iter()
+            .map(|slot| {
+                let count = slot.count.min(self.sketch.estimate(&slot.word));
+                Estimate {
+                    word: &slot.word,
+                    count,
+                    // the sketch can undercut the lower bound only if it is
+                    // wrong, which `delta` allows for
+                    error: count.saturating_sub(slot.count - slot.error),
+                }
+            })
+            .collect();
+        estimates.sort_unstable_by(|a, b| b.count.cmp(&a.count).then_with(|| a.word.cmp(b.word)));
+        if let Some(limit) = limit {
+            estimates.truncate(limit);
+        }
+        estimates
+    }
+}
+
+/// Approximate counts of the n-grams of `reader`, single words when `n` is 1.
+pub fn count_approx<R: BufRead>(
+    reader: R,
+    normalizer: &Normalizer,
+    n: usize,
+    config: &ApproxConfig,
+) -> io::Result<ApproxCounts> {
+    let mut counts = ApproxCounts::new(config);
+    counts.add_reader(reader, normalizer, n)?;
+    Ok(counts)
+}
+
+/// How an approximate top list measures up to exact counts of the same input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub k: usize,
+    /// Words in the approximate top `k` that belong in the exact top `k`,
+    /// counting ties with the exact `k`th word as belonging.
+    pub found: usize,
+    pub max_error: u64,
+    pub mean_error: f64,
+}
+
+pub fn compare(approx: &ApproxCounts, exact: &WordCounts, k: usize) -> Comparison {
+    let exact_top = exact.top(Some(k));
+    let cutoff = exact_top.last().map_or(0, |&(_, count)| count);
+    let estimates = approx.top(Some(k));
+    let errors: Vec<u64> = estimates
+        .iter()
+        .map(|e| e.count.abs_diff(exact.get(e.word)))
+        .collect();
+    Comparison {
+        k: estimates.len(),
+        found: estimates
+            .iter()
+            .filter(|e| exact.get(e.word) >= cutoff.max(1))
+            .count(),
+        max_error: errors.iter().copied().max().unwrap_or(0),
+        mean_error: errors.iter().sum::<u64>() as f64 / errors.len().max(1) as f64,
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of the approximate top {} are in the exact top {}; counts off by at most {} (mean {:.2})",
+            self.found, self.k, self.k, self.max_error, self.mean_error
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a skewed stream: word i occurs 1000 / (i + 1) times
+    fn zipf() -> Vec<String> {
+        let mut words = Vec::new();
+        for i in 0..500 {
+            for _ in 0..1000 / (i + 1) {
+                words.push(format!("w{}", i));
+            }
+        }
+        // interleave so the heavy hitters do not all arrive first
+        let mut mixed = Vec::with_capacity(words.len());
+        for offset in 0..7 {
+            mixed.extend(words.iter().skip(offset).step_by(7).cloned());
+        }
+        mixed
+    }
+
+    #[test]
+    fn test_sketch_never_underestimates() {
+        let words = zipf();
+        let mut sketch = CountMinSketch::new(0.01, 0.01);
+        let mut exact = WordCounts::new();
+        for word in &words {
+            sketch.add(word);
+            exact.add(word);
+        }
+        let bound = (0.01 * words.len() as f64) as u64;
+        for (word, count) in exact.top(None) {
+            let estimate = sketch.estimate(word);
+            assert!(estimate >= count);
+            assert!(
+                estimate - count <= bound,
+                "{} off by {}",
+                word,
+                estimate - count
+            );
+        }
+    }
+
+    #[test]
+    fn test_space_saving_keeps_heavy_hitters() {
+        let mut top = SpaceSaving::new(3);
+        for word in "a b a c a d b e a b".split(' ') {
+            top.add(word);
+        }
+        let mut slots: Vec<(&str, u64, u64)> = top
+            .heap
+            .iter()
+            .map(|s| (s.word.as_str(), s.count, s.error))
+            .collect();
+        slots.sort();
+        // `b` lost its slot twice and came back each time carrying the count
+        // of the slot it took over as possible error
+        assert_eq!(slots, [("a", 4, 0), ("b", 3, 2), ("e", 3, 2)]);
+        for (i, slot) in top.heap.iter().enumerate() {
+            assert_eq!(top.index[&slot.word], i);
+        }
+    }
+
+    #[test]
+    fn test_approximate_top_matches_exact_on_skewed_input() {
+        let words = zipf();
+        let config = ApproxConfig {
+            epsilon: 0.001,
+            delta: 0.01,
+            capacity: 50,
+        };
+        // two halves summarized apart and merged, as files are
+        let (left, right) = words.split_at(words.len() / 2);
+        let mut approx = ApproxCounts::new(&config);
+        let mut other = ApproxCounts::new(&config);
+        let mut exact = WordCounts::new();
+        for word in left {
+            approx.add(word);
+            exact.add(word);
+        }
+        for word in right {
+            other.add(word);
+            exact.add(word);
+        }
+        approx.merge(&other);
+
+        assert_eq!(approx.total(), exact.total());
+        for estimate in approx.top(Some(10)) {
+            let truth = exact.get(estimate.word);
+            assert!(estimate.count >= truth);
+            assert!(estimate.count - estimate.error <= truth);
+        }
+        let comparison = compare(&approx, &exact, 10);
+        assert_eq!(comparison.found, 10);
+        assert_eq!(approx.top(Some(1))[0].word, "w0");
+    }
+
+    #[test]
+    fn test_readers_add_up_in_one_summary() {
+        let config = ApproxConfig::default();
+        let normalizer = Normalizer::default();
+        let mut counts = ApproxCounts::new(&config);
+        counts
+            .add_reader("a b a".as_bytes(), &normalizer, 2)
+            .unwrap();
+        counts.add_reader("b a".as_bytes(), &normalizer, 2).unwrap();
+
+        // bigrams do not run from one input into the next
+        assert_eq!(counts.total(), 3);
+        let top: Vec<(&str, u64)> = counts.top(None).iter().map(|e| (e.word, e.count)).collect();
+        assert_eq!(top, [("b a", 2), ("a b", 1)]);
+    }
+}
@@ -0,0 +1,162 @@
+// Streaming word counts.
+//
+// Input is read a buffer at a time rather than a line at a time, so a file with
+// no newlines, or one larger than memory, is counted with memory proportional
+// to the number of distinct words. What counts as a word is up to the
+// `Normalizer`; bytes that are not valid UTF-8 are replaced rather than rejected.
+use crate::normalize::Normalizer;
+use std::collections::HashMap;
+use std::io::{self, BufRead};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WordCounts {
+    counts: HashMap<String, u64>,
+    total: u64,
+}
+
+impl WordCounts {
+    pub fn new() -> Self {
+        WordCounts::default()
+    }
+
+    pub fn add(&mut self, word: &str) {
+        self.total += 1;
+        // only allocate the key the first time a word is seen
+        match self.counts.get_mut(word) {
+            Some(count) => *count += 1,
+            None => {
+                self.counts.insert(word.to_string(), 1);
+            }
+        }
+    }
+
+    /// Adds `other`'s counts to these.
+    pub fn merge(&mut self, other: &WordCounts) {
+        self.total += other.total;
+        for (word, &count) in &other.counts {
+            match self.counts.get_mut(word) {
+                Some(mine) => *mine += count,
+                None => {
+                    self.counts.insert(word.clone(), count);
+                }
+            }
+        }
+    }
+
+    pub fn get(&self, word: &str) -> u64 {
+        self.counts.get(word).copied().unwrap_or(0)
+    }
+
+    /// Number of words counted, including repeats.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Number of different words.
+    pub fn distinct(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Words by descending count, ties in alphabetical order. `limit` keeps
+    /// only the first `limit`.
+    pub fn top(&self, limit: Option<usize>) -> Vec<(&str, u64)> {
+        let mut words: Vec<(&str, u64)> = self
+            .counts
+            .iter()
+            .map(|(word, &count)| (word.as_str(), count))
+            .collect();
+        words.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        if let Some(limit) = limit {
+            words.truncate(limit);
+        }
+        words
+    }
+}
+
+pub fn count_reader<R: BufRead>(reader: R, normalizer: &Normalizer) -> io::Result<WordCounts> {
+    let mut counts = WordCounts::new();
+    for_each_word(reader, normalizer, |word| counts.add(word))?;
+    Ok(counts)
+}
+
+/// Streams the normalized words of `reader` to `f`, in order.
+pub fn for_each_word<R: BufRead>(
+    mut reader: R,
+    normalizer: &Normalizer,
+    mut f: impl FnMut(&str),
+) -> io::Result<()> {
+    // bytes of the whitespace-free run in progress, which may span buffers
+    let mut word = Vec::new();
+    loop {
+        let buf = match reader.fill_buf() {
+            Ok([]) => break,
+            Ok(buf) => buf,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        for &byte in buf {
+            // ASCII whitespace never occurs inside a multi-byte UTF-8 character,
+            // so splitting bytes on it cannot cut a character in half
+            if byte.is_ascii_whitespace() {
+                flush(&mut word, normalizer, &mut f);
+            } else {
+                word.push(byte);
+            }
+        }
+        let len = buf.len();
+        reader.consume(len);
+    }
+    flush(&mut word, normalizer, &mut f);
+    Ok(())
+}
+
+fn flush(word: &mut Vec<u8>, normalizer: &Normalizer, f: &mut impl FnMut(&str)) {
+    if word.is_empty() {
+        return;
+    }
+    // the normalizer still splits on non-ASCII whitespace such as U+3000
+    normalizer.for_each_word(&String::from_utf8_lossy(word), &mut *f);
+    word.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+
+    #[test]
+    fn test_words_spanning_buffers_are_counted_once() {
+        let text = "alpha beta\nalpha\t\tgamma  alpha\u{3000}beta";
+        // a tiny buffer splits words across reads
+        let counts = count_reader(
+            BufReader::with_capacity(3, text.as_bytes()),
+            &Normalizer::default(),
+        )
+        .unwrap();
+
+        assert_eq!(counts.total(), 6);
+        assert_eq!(counts.distinct(), 3);
+        assert_eq!(
+            counts.top(None),
+            vec![("alpha", 3), ("beta", 2), ("gamma", 1)]
+        );
+        assert_eq!(counts.top(Some(1)), vec![("alpha", 3)]);
+    }
+
+    #[test]
+    fn test_merge_adds_counts() {
+        let normalizer = Normalizer::default();
+        let mut a = count_reader(&b"x y"[..], &normalizer).unwrap();
+        let b = count_reader(&b"y z z z"[..], &normalizer).unwrap();
+        a.merge(&b);
+
+        assert_eq!(a.total(), 6);
+        assert_eq!(a.top(None), vec![("z", 3), ("y", 2), ("x", 1)]);
+    }
+
+    #[test]
+    fn test_invalid_utf8_is_replaced_not_fatal() {
+        let counts = count_reader(&b"caf\xe9 caf\xe9"[..], &Normalizer::default()).unwrap();
+        assert_eq!(counts.get("caf\u{fffd}"), 2);
+    }
+}
@@ -0,0 +1,129 @@
+// Expands the paths given on the command line into the files to count.
+//
+// An argument can be a file, a directory, which is walked recursively, a glob
+// pattern or `-` for standard input. Directories are walked the way
+// `file_counts::visit_dir` does it: files at each level are listed directly and
+// subdirectories are visited in parallel with rayon. Unlike there, unreadable
+// directories are errors rather than silently skipped, since a missing part of
+// a corpus changes the counts. Symlinks to directories found along the way are
+// not followed, so a link back up the tree can't make the walk go round.
+use crate::source::STDIN;
+use anyhow::{Context, bail};
+use rayon::prelude::*;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Every file named by `args`, sorted and without duplicates.
+pub fn expand(args: &[String]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for arg in args {
+        let path = Path::new(arg);
+        if arg == STDIN {
+            files.push(path.to_path_buf());
+        } else if !path.exists() && is_glob(arg) {
+            let mut matched = false;
+            for entry in glob::glob(arg).with_context(|| format!("bad pattern {}", arg))? {
+                let path = entry?;
+                collect(&path, &mut files)?;
+                matched = true;
+            }
+            if !matched {
+                bail!("no files match {}", arg);
+            }
+        } else {
+            collect(path, &mut files)?;
+        }
+    }
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+fn is_glob(arg: &str) -> bool {
+    arg.contains(['*', '?', '['])
+}
+
+fn collect(path: &Path, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    let metadata = fs::metadata(path).with_context(|| format!("reading {}", path.display()))?;
+    if metadata.is_dir() {
+        files.extend(visit_dir(path)?);
+    } else {
+        files.push(path.to_path_buf());
+    }
+    Ok(())
+}
+
+fn visit_dir(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let entries = fs::read_dir(path)
+        .and_then(|entries| entries.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("listing {}", path.display()))?;
+
+    let mut dirs = Vec::new();
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry.path();
+        let file_type = entry
+            .file_type()
+            .with_context(|| format!("reading {}", path.display()))?;
+        if file_type.is_dir() {
+            dirs.push(path);
+        } else if !(file_type.is_symlink() && path.is_dir()) {
+            files.push(path);
+        }
+    }
+    // recurse into subdirs in parallel
+    let nested = dirs
+        .par_iter()
+        .map(|dir| visit_dir(dir))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    files.extend(nested.into_iter().flatten());
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directories_are_walked_and_globs_expanded() {
+        let root = std::env::temp_dir().join(format!("word_counter_inputs_{}", std::process::id()));
+        fs::create_dir_all(root.join("nested/deeper")).unwrap();
+        for file in ["a.txt", "b.md", "nested/c.txt", "nested/deeper/d.txt"] {
+            fs::write(root.join(file), "words").unwrap();
+        }
+        let arg = |rel: &str| root.join(rel).to_string_lossy().into_owned();
+
+        let walked = expand(&[arg("nested"), arg("nested/c.txt")]).unwrap();
+        assert_eq!(
+            walked,
+            vec![root.join("nested/c.txt"), root.join("nested/deeper/d.txt")]
+        );
+
+        let globbed = expand(&[arg("*.txt")]).unwrap();
+        assert_eq!(globbed, vec![root.join("a.txt")]);
+
+        assert!(expand(&[arg("missing.txt")]).is_err());
+        assert!(expand(&[arg("*.rs")]).is_err());
+        // stdin is taken on trust, and only once
+        assert_eq!(
+            expand(&["-".to_string(), arg("a.txt"), "-".to_string()]).unwrap(),
+            vec![root.join("a.txt"), PathBuf::from("-")]
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_directories_are_not_followed() {
+        let root = std::env::temp_dir().join(format!("word_counter_links_{}", std::process::id()));
+        fs::create_dir_all(root.join("nested")).unwrap();
+        fs::write(root.join("nested/a.txt"), "words").unwrap();
+        // a cycle back to the top, and a link to a file, which is counted
+        std::os::unix::fs::symlink(&root, root.join("nested/up")).unwrap();
+        std::os::unix::fs::symlink(root.join("nested/a.txt"), root.join("b.txt")).unwrap();
+
+        let walked = expand(&[root.to_string_lossy().into_owned()]).unwrap();
+        assert_eq!(walked, vec![root.join("b.txt"), root.join("nested/a.txt")]);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,7 @@
+pub mod approx;
+pub mod count;
+pub mod inputs;
+pub mod ngram;
+pub mod normalize;
+pub mod report;
+pub mod source;
@@ -1,32 +1,290 @@
-use clap::Parser;
-use std::collections::HashMap;
-use std::fs::File;
+use anyhow::{Context, bail};
+use clap::builder::RangedU64ValueParser;
+use clap::{Args, Parser, ValueEnum};
+use rayon::prelude::*;
 use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use word_counter::approx::{ApproxConfig, ApproxCounts, compare};
+use word_counter::inputs::expand;
+use word_counter::ngram::{NgramCounts, count_ngrams};
+use word_counter::normalize::{Normalizer, Segmentation};
+use word_counter::report::{
+    ALL_FILES, OutputFormat, WordRow, ranked, ranked_collocations, ranked_estimates, write_rows,
+};
+use word_counter::source::{self, is_stdin};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
 struct Cli {
-    #[arg(value_name = "file", short = 'f')]
-    filename: String,
+    /// Files, directories (searched recursively), glob patterns or `-` for stdin.
+    /// Gzip, Zstandard and bzip2 input is decompressed.
+    #[arg(value_name = "file", short = 'f', required = true, num_args = 1..)]
+    inputs: Vec<String>,
+    /// Also list the top words of each file.
+    #[arg(long)]
+    per_file: bool,
+    /// Only print the N most frequent words.
+    #[arg(short = 'n', long)]
+    top: Option<usize>,
+    /// Count runs of N consecutive words rather than single words.
+    #[arg(long, value_name = "N", default_value_t = 1,
+          value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    ngram: usize,
+    /// Rank n-grams by pointwise mutual information rather than count.
+    #[arg(long)]
+    collocations: bool,
+    /// Fewest occurrences an n-gram needs to be ranked as a collocation.
+    #[arg(long, default_value_t = 5)]
+    min_count: u64,
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+    #[command(flatten)]
+    normalize: NormalizeArgs,
+    #[command(flatten)]
+    approx: ApproxArgs,
 }
 
-// enter a filename.
-// reads the file and counts the words.
-fn main() {
-    let filename = Cli::parse().filename;
-    println!("{:?}", filename);
+#[derive(Args, Debug)]
+struct NormalizeArgs {
+    /// Fold case so "Rust" and "RUST" count as one word.
+    #[arg(short = 'i', long)]
+    fold_case: bool,
+    /// Trim punctuation from the ends of words.
+    #[arg(short = 'p', long)]
+    strip_punctuation: bool,
+    /// Split words at Unicode word boundaries rather than whitespace.
+    #[arg(short = 'u', long)]
+    unicode_words: bool,
+    /// File of words to leave out, whitespace separated; `#` starts a comment line.
+    #[arg(long, value_name = "file")]
+    stopwords: Option<PathBuf>,
+    /// Drop words with fewer characters than this.
+    #[arg(long, default_value_t = 0)]
+    min_len: usize,
+}
+
+impl NormalizeArgs {
+    fn normalizer(&self) -> anyhow::Result<Normalizer> {
+        let mut normalizer = Normalizer::default();
+        if self.unicode_words {
+            normalizer.segmentation = Segmentation::Unicode;
+        }
+        normalizer.case_fold = self.fold_case;
+        normalizer.strip_punctuation = self.strip_punctuation;
+        normalizer.min_len = self.min_len;
+        match &self.stopwords {
+            Some(path) => normalizer
+                .with_stopwords_file(path)
+                .with_context(|| format!("reading stopwords {}", path.display())),
+            None => Ok(normalizer),
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct ApproxArgs {
+    /// Count in bounded memory with a Count-Min Sketch and a Space-Saving top
+    /// list; counts come with the most they may be over.
+    #[arg(long, conflicts_with = "collocations")]
+    approx: bool,
+    /// Largest sketch overestimate, as a fraction of all words counted.
+    #[arg(long, default_value_t = 1e-4, requires = "approx")]
+    epsilon: f64,
+    /// Chance of a sketch estimate going over that bound.
+    #[arg(long, default_value_t = 0.01, requires = "approx")]
+    delta: f64,
+    /// Words tracked as top candidates.
+    #[arg(long, default_value_t = 10_000, requires = "approx",
+          value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    capacity: usize,
+    /// Also count exactly and report how the approximate top list compares,
+    /// on stderr. Reads every input twice, so stdin can't be one.
+    #[arg(long, requires = "approx")]
+    compare: bool,
+}
+
+impl ApproxArgs {
+    fn config(&self) -> anyhow::Result<Option<ApproxConfig>> {
+        if !self.approx {
+            return Ok(None);
+        }
+        for (name, value) in [("epsilon", self.epsilon), ("delta", self.delta)] {
+            if !(value > 0.0 && value < 1.0) {
+                bail!("--{} must be between 0 and 1, got {}", name, value);
+            }
+        }
+        Ok(Some(ApproxConfig {
+            epsilon: self.epsilon,
+            delta: self.delta,
+            capacity: self.capacity,
+        }))
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum, Debug)]
+enum Format {
+    Text,
+    Csv,
+    Json,
+}
+
+impl From<Format> for OutputFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Text => OutputFormat::Text,
+            Format::Csv => OutputFormat::Csv,
+            Format::Json => OutputFormat::Json,
+        }
+    }
+}
+
+enum Counts {
+    Exact(NgramCounts),
+    Approx(ApproxCounts),
+}
 
-    let file = File::open(filename);
-    let reader = std::io::BufReader::new(file.unwrap());
+impl Counts {
+    fn new(n: usize, approx: Option<&ApproxConfig>) -> Counts {
+        match approx {
+            Some(config) => Counts::Approx(ApproxCounts::new(config)),
+            None => Counts::Exact(NgramCounts::new(n)),
+        }
+    }
 
-    let mut word_count: HashMap<String, i32> = HashMap::new();
-    for line in reader.lines() {
-        let aline = line.unwrap().to_string();
-        let words: Vec<String> = aline.split_whitespace().map(|s| s.to_string()).collect();
-        for word in words {
-            *word_count.entry(word).or_insert(0) += 1;
+    fn merge(&mut self, other: &Counts) {
+        match (self, other) {
+            (Counts::Exact(mine), Counts::Exact(theirs)) => mine.merge(theirs),
+            (Counts::Approx(mine), Counts::Approx(theirs)) => mine.merge(theirs),
+            _ => unreachable!("every file is counted the same way"),
         }
-        
     }
-    println!("{:?}", word_count);
+}
+
+fn open(path: &Path) -> anyhow::Result<Box<dyn BufRead>> {
+    source::open(path).with_context(|| format!("opening {}", path.display()))
+}
+
+fn count_file(path: &Path, normalizer: &Normalizer, n: usize) -> anyhow::Result<NgramCounts> {
+    count_ngrams(open(path)?, normalizer, n).with_context(|| format!("reading {}", path.display()))
+}
+
+fn add_file_approx(
+    counts: &mut ApproxCounts,
+    path: &Path,
+    normalizer: &Normalizer,
+    n: usize,
+) -> anyhow::Result<()> {
+    counts
+        .add_reader(open(path)?, normalizer, n)
+        .with_context(|| format!("reading {}", path.display()))
+}
+
+// Counts every file in parallel into a single total. Each file is merged in as
+// soon as it is counted, so only one total per worker is held at a time rather
+// than a count for every file.
+fn count_all<T: Send>(
+    files: &[PathBuf],
+    empty: impl Fn() -> T + Sync + Send,
+    add: impl Fn(&mut T, &Path) -> anyhow::Result<()> + Sync + Send,
+    merge: impl Fn(&mut T, &T) + Sync + Send,
+) -> anyhow::Result<T> {
+    files
+        .par_iter()
+        .try_fold(&empty, |mut total, path| {
+            add(&mut total, path)?;
+            Ok(total)
+        })
+        .try_reduce(&empty, |mut total, other| {
+            merge(&mut total, &other);
+            Ok(total)
+        })
+}
+
+fn rows<'a>(cli: &Cli, file: Option<&'a str>, counts: &'a Counts) -> Vec<WordRow<'a>> {
+    match counts {
+        Counts::Exact(counts) if cli.collocations => {
+            let mut collocations = counts.collocations(cli.min_count);
+            if let Some(top) = cli.top {
+                collocations.truncate(top);
+            }
+            ranked_collocations(file, &collocations)
+        }
+        Counts::Exact(counts) => ranked(file, &counts.ngrams().top(cli.top)),
+        Counts::Approx(counts) => ranked_estimates(file, &counts.top(cli.top)),
+    }
+}
+
+// enter files, directories or globs.
+// counts the words (or n-grams) of every file in parallel, most frequent first.
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    if cli.collocations && cli.ngram < 2 {
+        bail!("--collocations needs --ngram 2 or more");
+    }
+    let normalizer = cli.normalize.normalizer()?;
+    let approx = cli.approx.config()?;
+
+    let files = expand(&cli.inputs)?;
+    if cli.approx.compare && files.iter().any(|f| is_stdin(f)) {
+        bail!("--compare reads its inputs twice and can't take stdin");
+    }
+    let count = |path: &Path| match &approx {
+        Some(config) => {
+            let mut counts = ApproxCounts::new(config);
+            add_file_approx(&mut counts, path, &normalizer, cli.ngram)?;
+            Ok(Counts::Approx(counts))
+        }
+        None => count_file(path, &normalizer, cli.ngram).map(Counts::Exact),
+    };
+    let empty = || Counts::new(cli.ngram, approx.as_ref());
+    // each file's own counts are only kept when they are listed
+    let (per_file, total) = if cli.per_file {
+        let per_file = files
+            .par_iter()
+            .map(|path| count(path))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut total = empty();
+        for counts in &per_file {
+            total.merge(counts);
+        }
+        (per_file, total)
+    } else {
+        // approximate counts go straight into the worker's own sketch, so
+        // memory stays fixed however many files there are
+        let add = |total: &mut Counts, path: &Path| match total {
+            Counts::Approx(total) => add_file_approx(total, path, &normalizer, cli.ngram),
+            Counts::Exact(_) => {
+                total.merge(&count(path)?);
+                Ok(())
+            }
+        };
+        (Vec::new(), count_all(&files, empty, add, Counts::merge)?)
+    };
+
+    let names: Vec<String> = files.iter().map(|f| f.display().to_string()).collect();
+    let mut output = Vec::new();
+    if cli.per_file {
+        for (name, counts) in names.iter().zip(&per_file) {
+            output.extend(rows(&cli, Some(name), counts));
+        }
+    }
+    output.extend(rows(&cli, cli.per_file.then_some(ALL_FILES), &total));
+    write_rows(&output, cli.format.into(), std::io::stdout().lock())?;
+
+    if let Counts::Approx(approx) = &total
+        && cli.approx.compare
+    {
+        let exact = count_all(
+            &files,
+            || NgramCounts::new(cli.ngram),
+            |exact, path| {
+                exact.merge(&count_file(path, &normalizer, cli.ngram)?);
+                Ok(())
+            },
+            NgramCounts::merge,
+        )?;
+        let k = cli.top.unwrap_or(10);
+        eprintln!("{}", compare(approx, exact.ngrams(), k));
+    }
+    Ok(())
 }
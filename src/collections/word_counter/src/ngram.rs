@@ -0,0 +1,193 @@
+// N-gram counts and collocation scoring.
+//
+// N-grams are runs of `n` consecutive words as the `Normalizer` produces them,
+// so they follow the same tokenizing rules as single-word counts. They are kept
+// as their words joined by a single space. Runs do not cross file boundaries
+// but do cross lines and sentences.
+//
+// Collocations are scored by pointwise mutual information: how much more often
+// the words occur together than they would by chance,
+//
+//     pmi = log2(p(w1 .. wn) / (p(w1) * .. * p(wn)))
+//
+// with each probability estimated from counts. PMI overrates rare n-grams, so
+// only those seen at least `min_count` times are scored.
+use crate::count::{WordCounts, for_each_word};
+use crate::normalize::Normalizer;
+use std::collections::VecDeque;
+use std::io::{self, BufRead};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NgramCounts {
+    n: usize,
+    words: WordCounts,
+    // empty when `n` is 1, where the n-grams are the words
+    ngrams: WordCounts,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Collocation<'a> {
+    pub ngram: &'a str,
+    pub count: u64,
+    pub pmi: f64,
+}
+
+impl NgramCounts {
+    pub fn new(n: usize) -> Self {
+        assert!(n > 0, "n-grams need at least one word");
+        NgramCounts {
+            n,
+            words: WordCounts::new(),
+            ngrams: WordCounts::new(),
+        }
+    }
+
+    pub fn n(&self) -> usize {
+        self.n
+    }
+
+    /// Single-word counts, whatever `n` is.
+    pub fn words(&self) -> &WordCounts {
+        &self.words
+    }
+
+    pub fn ngrams(&self) -> &WordCounts {
+        if self.n == 1 {
+            &self.words
+        } else {
+            &self.ngrams
+        }
+    }
+
+    pub fn merge(&mut self, other: &NgramCounts) {
+        assert_eq!(self.n, other.n, "merging counts of different n-grams");
+        self.words.merge(&other.words);
+        self.ngrams.merge(&other.ngrams);
+    }
+
+    /// N-grams seen at least `min_count` times by descending PMI, then
+    /// descending count, then alphabetically.
+    pub fn collocations(&self, min_count: u64) -> Vec<Collocation<'_>> {
+        let ngrams = self.ngrams();
+        let (words_total, ngrams_total) = (self.words.total() as f64, ngrams.total() as f64);
+        let mut scored: Vec<Collocation> = ngrams
+            .top(None)
+            .into_iter()
+            .filter(|&(_, count)| count >= min_count.max(1))
+            .map(|(ngram, count)| {
+                // every word of a counted n-gram has been counted too
+                let chance: f64 = ngram
+                    .split(' ')
+                    .map(|word| self.words.get(word) as f64 / words_total)
+                    .product();
+                let pmi = (count as f64 / ngrams_total / chance).log2();
+                Collocation { ngram, count, pmi }
+            })
+            .collect();
+        scored.sort_by(|a, b| {
+            b.pmi
+                .total_cmp(&a.pmi)
+                .then_with(|| b.count.cmp(&a.count))
+                .then_with(|| a.ngram.cmp(b.ngram))
+        });
+        scored
+    }
+}
+
+/// Slides over a stream of words, yielding each n-gram once it is complete.
+#[derive(Debug, Clone)]
+pub struct NgramWindow {
+    n: usize,
+    words: VecDeque<String>,
+    ngram: String,
+}
+
+impl NgramWindow {
+    pub fn new(n: usize) -> Self {
+        assert!(n > 0, "n-grams need at least one word");
+        NgramWindow {
+            n,
+            words: VecDeque::with_capacity(n),
+            ngram: String::new(),
+        }
+    }
+
+    /// The n-gram ending at `word`, or `None` until `n` words have been seen.
+    pub fn push(&mut self, word: &str) -> Option<&str> {
+        // reuse the word sliding out of the window
+        let mut slot = if self.words.len() == self.n {
+            self.words.pop_front().unwrap_or_default()
+        } else {
+            String::new()
+        };
+        slot.clear();
+        slot.push_str(word);
+        self.words.push_back(slot);
+        if self.words.len() < self.n {
+            return None;
+        }
+        self.ngram.clear();
+        for (i, word) in self.words.iter().enumerate() {
+            if i > 0 {
+                self.ngram.push(' ');
+            }
+            self.ngram.push_str(word);
+        }
+        Some(&self.ngram)
+    }
+}
+
+pub fn count_ngrams<R: BufRead>(
+    reader: R,
+    normalizer: &Normalizer,
+    n: usize,
+) -> io::Result<NgramCounts> {
+    let mut counts = NgramCounts::new(n);
+    let mut window = NgramWindow::new(n);
+    for_each_word(reader, normalizer, |word| {
+        counts.words.add(word);
+        if n > 1
+            && let Some(ngram) = window.push(word)
+        {
+            counts.ngrams.add(ngram);
+        }
+    })?;
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bigrams_slide_over_normalized_words() {
+        let mut normalizer = Normalizer::default();
+        normalizer.case_fold = true;
+        let counts = count_ngrams(&b"New York\nnew york NEW"[..], &normalizer, 2).unwrap();
+
+        assert_eq!(counts.words().total(), 5);
+        assert_eq!(
+            counts.ngrams().top(None),
+            vec![("new york", 2), ("york new", 2)]
+        );
+        // unigram counting is unchanged
+        let unigrams = count_ngrams(&b"a b a"[..], &normalizer, 1).unwrap();
+        assert_eq!(unigrams.ngrams().top(None), vec![("a", 2), ("b", 1)]);
+    }
+
+    #[test]
+    fn test_pmi_ranks_fixed_phrases_over_common_pairs() {
+        let text = "we ate a hot dog and a bun and a pie and we ate a hot dog and a bun";
+        let counts = count_ngrams(text.as_bytes(), &Normalizer::default(), 2).unwrap();
+        let scored = counts.collocations(2);
+
+        let ranked: Vec<&str> = scored.iter().map(|c| c.ngram).collect();
+        assert_eq!(&ranked[..2], ["hot dog", "we ate"]);
+        // the most frequent pair is the least informative
+        assert_eq!(ranked.last(), Some(&"and a"));
+        // 20 words, 19 bigrams: p(hot dog) = 2/19, p(hot) = p(dog) = 2/20
+        let expected = ((2.0_f64 / 19.0) / (0.1 * 0.1)).log2();
+        assert!((scored[0].pmi - expected).abs() < 1e-12);
+        assert_eq!(scored.len(), 7);
+    }
+}
@@ -0,0 +1,141 @@
+// Turns raw text into the words that get counted.
+//
+// The default keeps every whitespace-separated run exactly as written. Each
+// option narrows that down: UAX #29 word segmentation instead of whitespace
+// splitting, trimming punctuation off word ends, full Unicode case folding (so
+// "Straße" and "STRASSE" agree), a minimum length in characters and a stopword
+// list. Stopwords go through the same folding as the text, so a list written in
+// lower case matches capitalized words when folding is on.
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+use unicode_segmentation::UnicodeSegmentation;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Segmentation {
+    /// Split on whitespace only.
+    #[default]
+    Whitespace,
+    /// Unicode word boundaries, which also drop runs of pure punctuation.
+    Unicode,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Normalizer {
+    pub segmentation: Segmentation,
+    pub case_fold: bool,
+    pub strip_punctuation: bool,
+    /// Shorter words, counted in characters, are dropped.
+    pub min_len: usize,
+    stopwords: HashSet<String>,
+}
+
+impl Normalizer {
+    /// Stopwords are whitespace separated; lines starting with `#` are comments.
+    /// They are folded as `case_fold` is set now, so set that first.
+    pub fn with_stopwords(mut self, text: &str) -> Self {
+        let words = text
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .flat_map(str::split_whitespace);
+        for word in words {
+            let word = self.fold(word).into_owned();
+            self.stopwords.insert(word);
+        }
+        self
+    }
+
+    pub fn with_stopwords_file(self, path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Ok(self.with_stopwords(&text))
+    }
+
+    fn fold<'a>(&self, word: &'a str) -> Cow<'a, str> {
+        if self.case_fold {
+            Cow::Owned(caseless::default_case_fold_str(word))
+        } else {
+            Cow::Borrowed(word)
+        }
+    }
+
+    /// Calls `f` with each word of `text` that survives normalization.
+    pub fn for_each_word(&self, text: &str, mut f: impl FnMut(&str)) {
+        let mut emit = |word: &str| {
+            let word = if self.strip_punctuation {
+                word.trim_matches(|c: char| !c.is_alphanumeric())
+            } else {
+                word
+            };
+            if word.is_empty() {
+                return;
+            }
+            let word = self.fold(word);
+            if word.chars().count() < self.min_len || self.stopwords.contains(word.as_ref()) {
+                return;
+            }
+            f(&word);
+        };
+        match self.segmentation {
+            Segmentation::Whitespace => text.split_whitespace().for_each(&mut emit),
+            Segmentation::Unicode => text.unicode_words().for_each(&mut emit),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(normalizer: &Normalizer, text: &str) -> Vec<String> {
+        let mut words = Vec::new();
+        normalizer.for_each_word(text, |w| words.push(w.to_string()));
+        words
+    }
+
+    #[test]
+    fn test_default_keeps_words_as_written() {
+        let normalizer = Normalizer::default();
+        assert_eq!(
+            words(&normalizer, "Rust rust, rust."),
+            ["Rust", "rust,", "rust."]
+        );
+    }
+
+    #[test]
+    fn test_folding_and_stripping_merge_variants() {
+        let normalizer = Normalizer {
+            case_fold: true,
+            strip_punctuation: true,
+            ..Normalizer::default()
+        };
+        assert_eq!(
+            words(&normalizer, "Rust rust, \"rust.\" Straße STRASSE -- don't"),
+            ["rust", "rust", "rust", "strasse", "strasse", "don't"]
+        );
+    }
+
+    #[test]
+    fn test_unicode_segmentation_splits_punctuation() {
+        let normalizer = Normalizer {
+            segmentation: Segmentation::Unicode,
+            ..Normalizer::default()
+        };
+        assert_eq!(
+            words(&normalizer, "hello,world! it's 3.14 -- done"),
+            ["hello", "world", "it's", "3.14", "done"]
+        );
+    }
+
+    #[test]
+    fn test_stopwords_are_folded_and_short_words_dropped() {
+        let normalizer = Normalizer {
+            case_fold: true,
+            min_len: 3,
+            ..Normalizer::default()
+        }
+        .with_stopwords("# common words\nthe AND\n");
+        assert_eq!(words(&normalizer, "The cat and a Dog"), ["cat", "dog"]);
+    }
+}
@@ -0,0 +1,247 @@
+// Writes word counts as a text table, CSV or JSON.
+//
+// Rows can carry the file they were counted in. When they do, the text table
+// gets a heading per file and CSV and JSON rows get a `file` column, with `*`
+// standing for all files together. Collocation rows also carry their PMI and
+// approximate counts the most they may be over, shown as `±error` in text.
+use crate::approx::Estimate;
+use crate::ngram::Collocation;
+use serde::Serialize;
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Csv,
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WordRow<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<&'a str>,
+    pub rank: usize,
+    pub word: &'a str,
+    pub count: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pmi: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<u64>,
+}
+
+pub const ALL_FILES: &str = "*";
+
+/// Numbers `(word, count)` pairs from 1 in the order given.
+pub fn ranked<'a>(file: Option<&'a str>, words: &[(&'a str, u64)]) -> Vec<WordRow<'a>> {
+    words
+        .iter()
+        .enumerate()
+        .map(|(i, &(word, count))| WordRow {
+            file,
+            rank: i + 1,
+            word,
+            count,
+            pmi: None,
+            error: None,
+        })
+        .collect()
+}
+
+/// Numbers collocations from 1 in the order given.
+pub fn ranked_collocations<'a>(
+    file: Option<&'a str>,
+    collocations: &[Collocation<'a>],
+) -> Vec<WordRow<'a>> {
+    collocations
+        .iter()
+        .enumerate()
+        .map(|(i, c)| WordRow {
+            file,
+            rank: i + 1,
+            word: c.ngram,
+            count: c.count,
+            pmi: Some(c.pmi),
+            error: None,
+        })
+        .collect()
+}
+
+/// Numbers approximate counts from 1 in the order given.
+pub fn ranked_estimates<'a>(file: Option<&'a str>, estimates: &[Estimate<'a>]) -> Vec<WordRow<'a>> {
+    estimates
+        .iter()
+        .enumerate()
+        .map(|(i, e)| WordRow {
+            file,
+            rank: i + 1,
+            word: e.word,
+            count: e.count,
+            pmi: None,
+            error: Some(e.error),
+        })
+        .collect()
+}
+
+pub fn write_rows<W: Write>(
+    rows: &[WordRow],
+    format: OutputFormat,
+    writer: W,
+) -> anyhow::Result<()> {
+    let mut writer = writer;
+    match format {
+        OutputFormat::Text => {
+            let width = rows
+                .iter()
+                .map(|r| r.word.chars().count())
+                .max()
+                .unwrap_or(0);
+            let count_width = rows
+                .iter()
+                .map(|r| r.count.to_string().len())
+                .max()
+                .unwrap_or(0);
+            let mut file = None;
+            for row in rows {
+                if row.file != file {
+                    file = row.file;
+                    match file {
+                        Some(ALL_FILES) => writeln!(writer, "== all files ==")?,
+                        Some(name) => writeln!(writer, "== {} ==", name)?,
+                        None => {}
+                    }
+                }
+                match (row.pmi, row.error) {
+                    (Some(pmi), _) => writeln!(
+                        writer,
+                        "{:>5}  {:<width$}  {:>count_width$}  {:.3}",
+                        row.rank, row.word, row.count, pmi
+                    )?,
+                    (None, Some(error)) => writeln!(
+                        writer,
+                        "{:>5}  {:<width$}  {:>count_width$}  ±{}",
+                        row.rank, row.word, row.count, error
+                    )?,
+                    (None, None) => writeln!(
+                        writer,
+                        "{:>5}  {:<width$}  {}",
+                        row.rank, row.word, row.count
+                    )?,
+                }
+            }
+        }
+        OutputFormat::Csv => {
+            let mut csv = csv::Writer::from_writer(writer);
+            for row in rows {
+                csv.serialize(row)?;
+            }
+            csv.flush()?;
+        }
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, rows)?;
+            writeln!(writer)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_has_header_and_ranks() {
+        let rows = ranked(None, &[("the", 3), ("a, b", 1)]);
+        let mut out = Vec::new();
+        write_rows(&rows, OutputFormat::Csv, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "rank,word,count\n1,the,3\n2,\"a, b\",1\n"
+        );
+    }
+
+    #[test]
+    fn test_text_columns_line_up() {
+        let rows = ranked(None, &[("the", 3), ("rust", 1)]);
+        let mut out = Vec::new();
+        write_rows(&rows, OutputFormat::Text, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "    1  the   3\n    2  rust  1\n"
+        );
+    }
+
+    #[test]
+    fn test_per_file_rows_carry_the_file() {
+        let mut rows = ranked(Some("a.txt"), &[("x", 2)]);
+        rows.extend(ranked(Some(ALL_FILES), &[("x", 5)]));
+
+        let mut csv = Vec::new();
+        write_rows(&rows, OutputFormat::Csv, &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "file,rank,word,count\na.txt,1,x,2\n*,1,x,5\n"
+        );
+
+        let mut text = Vec::new();
+        write_rows(&rows, OutputFormat::Text, &mut text).unwrap();
+        assert_eq!(
+            String::from_utf8(text).unwrap(),
+            "== a.txt ==\n    1  x  2\n== all files ==\n    1  x  5\n"
+        );
+    }
+
+    #[test]
+    fn test_collocations_add_a_pmi_column() {
+        let collocations = [
+            Collocation {
+                ngram: "hot dog",
+                count: 2,
+                pmi: 3.3959,
+            },
+            Collocation {
+                ngram: "and a",
+                count: 13,
+                pmi: 0.5,
+            },
+        ];
+        let rows = ranked_collocations(None, &collocations);
+
+        let mut text = Vec::new();
+        write_rows(&rows, OutputFormat::Text, &mut text).unwrap();
+        assert_eq!(
+            String::from_utf8(text).unwrap(),
+            "    1  hot dog   2  3.396\n    2  and a    13  0.500\n"
+        );
+
+        let mut csv = Vec::new();
+        write_rows(&rows[..1], OutputFormat::Csv, &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "rank,word,count,pmi\n1,hot dog,2,3.3959\n"
+        );
+    }
+
+    #[test]
+    fn test_estimates_show_their_error() {
+        let estimates = [
+            Estimate {
+                word: "the",
+                count: 120,
+                error: 0,
+            },
+            Estimate {
+                word: "of",
+                count: 7,
+                error: 3,
+            },
+        ];
+        let rows = ranked_estimates(None, &estimates);
+
+        let mut text = Vec::new();
+        write_rows(&rows, OutputFormat::Text, &mut text).unwrap();
+        assert_eq!(
+            String::from_utf8(text).unwrap(),
+            "    1  the  120  ±0\n    2  of     7  ±3\n"
+        );
+    }
+}
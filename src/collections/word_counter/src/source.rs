@@ -0,0 +1,178 @@
+// Opens an input for reading, decompressing it when it needs it.
+//
+// `-` reads standard input. Gzip, Zstandard and bzip2 streams are recognised by
+// their magic bytes, so a compressed file is read correctly whatever it is
+// called and so is compressed stdin. Input without known magic bytes falls back
+// on its extension, so a damaged `.gz` fails to decode rather than being
+// counted as text. Multi-member gzip and bzip2 files, as written by `pigz` and
+// `pbzip2`, are read to the end.
+use bzip2::bufread::MultiBzDecoder;
+use flate2::bufread::MultiGzDecoder;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+pub const STDIN: &str = "-";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    pub fn from_magic(head: &[u8]) -> Option<Compression> {
+        if head.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if head.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else if let [b'B', b'Z', b'h', b'1'..=b'9', ..] = head {
+            // the digit is the block size, which keeps text starting "BZh" out
+            Some(Compression::Bzip2)
+        } else {
+            None
+        }
+    }
+
+    pub fn from_extension(path: &Path) -> Compression {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            Some("bz2") => Compression::Bzip2,
+            _ => Compression::None,
+        }
+    }
+}
+
+pub fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == STDIN
+}
+
+/// A reader over the text of `path`, or of stdin for `-`.
+pub fn open(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    if is_stdin(path) {
+        decompress(io::stdin().lock(), path)
+    } else {
+        decompress(BufReader::new(File::open(path)?), path)
+    }
+}
+
+// the longest magic number recognised
+const MAGIC_LEN: usize = 4;
+
+/// Wraps `reader` in the decoder its first bytes call for, falling back on the
+/// extension of `path`.
+pub fn decompress<R: BufRead + 'static>(
+    mut reader: R,
+    path: &Path,
+) -> io::Result<Box<dyn BufRead>> {
+    // a pipe can hand over less than a magic number in one read, so the head is
+    // read until it holds one or the input ends, then put back in front
+    let mut head = Vec::with_capacity(MAGIC_LEN);
+    while head.len() < MAGIC_LEN {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+        let take = buf.len().min(MAGIC_LEN - head.len());
+        head.extend_from_slice(&buf[..take]);
+        reader.consume(take);
+    }
+    let compression = match Compression::from_magic(&head) {
+        Some(compression) => compression,
+        None => Compression::from_extension(path),
+    };
+    let reader = io::Cursor::new(head).chain(reader);
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+        Compression::Zstd => Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?)),
+        Compression::Bzip2 => Box::new(BufReader::new(MultiBzDecoder::new(reader))),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    const TEXT: &str = "the quick brown fox jumps over the lazy dog\n";
+
+    fn read_all(compressed: Vec<u8>, name: &str) -> String {
+        let mut text = String::new();
+        decompress(io::Cursor::new(compressed), Path::new(name))
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[test]
+    fn test_each_format_is_detected_by_its_magic_bytes() {
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(TEXT.as_bytes()).unwrap();
+        let gzip = gzip.finish().unwrap();
+        let zstd = zstd::encode_all(TEXT.as_bytes(), 0).unwrap();
+        let mut bzip2 = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        bzip2.write_all(TEXT.as_bytes()).unwrap();
+        let bzip2 = bzip2.finish().unwrap();
+
+        // names deliberately say nothing, or the wrong thing
+        assert_eq!(read_all(gzip, "archive"), TEXT);
+        assert_eq!(read_all(zstd, "archive.txt"), TEXT);
+        assert_eq!(read_all(bzip2, "archive.gz"), TEXT);
+        assert_eq!(read_all(TEXT.as_bytes().to_vec(), "plain.txt"), TEXT);
+    }
+
+    #[test]
+    fn test_text_that_starts_like_bzip2_is_plain() {
+        assert_eq!(Compression::from_magic(b"BZh9"), Some(Compression::Bzip2));
+        assert_eq!(Compression::from_magic(b"BZhang wrote this"), None);
+        let text = "BZhang wrote this\n";
+        assert_eq!(read_all(text.as_bytes().to_vec(), "names.txt"), text);
+    }
+
+    // hands over one byte per read, as a slow pipe might
+    struct Trickle(io::Cursor<Vec<u8>>);
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let end = buf.len().min(1);
+            self.0.read(&mut buf[..end])
+        }
+    }
+
+    #[test]
+    fn test_magic_split_across_reads_is_still_detected() {
+        let zstd = zstd::encode_all(TEXT.as_bytes(), 0).unwrap();
+        let reader = BufReader::new(Trickle(io::Cursor::new(zstd)));
+        let mut text = String::new();
+        decompress(reader, Path::new(STDIN))
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, TEXT);
+
+        // input shorter than any magic number is read as it is
+        let reader = BufReader::new(Trickle(io::Cursor::new(b"BZ".to_vec())));
+        let mut text = String::new();
+        decompress(reader, Path::new(STDIN))
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "BZ");
+    }
+
+    #[test]
+    fn test_concatenated_gzip_members_are_all_read() {
+        let mut compressed = Vec::new();
+        for _ in 0..2 {
+            let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+            gzip.write_all(TEXT.as_bytes()).unwrap();
+            compressed.extend(gzip.finish().unwrap());
+        }
+        assert_eq!(read_all(compressed, "twice.gz"), TEXT.repeat(2));
+    }
+}
@@ -0,0 +1,340 @@
+// Currencies and their named coins and bills, loaded from a TOML file.
+//
+// Amounts are whole minor units (cents, pence, yen) throughout; only parsing
+// and formatting deal in decimals.
+use crate::{ChangeError, Strategy, coin_change_with, is_canonical};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::{fmt, fs, io};
+
+// the built-in registry, used when no config file is given
+pub const DEFAULT_CONFIG: &str = include_str!("../currencies.toml");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Coin,
+    Bill,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Denomination {
+    pub name: String,
+    pub value: u32,
+    pub kind: Kind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Currency {
+    #[serde(skip)]
+    pub code: String,
+    pub name: String,
+    pub minor_units: u32,
+    pub cash_rounding: Option<u32>,
+    pub denominations: Vec<Denomination>,
+    // checked once when the registry is loaded; `None` when too large to check
+    #[serde(skip)]
+    canonical: Option<bool>,
+}
+
+/// How many of one denomination make up part of some change.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Piece<'a> {
+    #[serde(flatten)]
+    pub denomination: &'a Denomination,
+    pub count: u32,
+}
+
+impl Currency {
+    fn scale(&self) -> u32 {
+        10u32.pow(self.minor_units)
+    }
+
+    /// Parses "12.34" (or "12" or "12.3") into minor units.
+    pub fn parse_amount(&self, text: &str) -> Result<u32, String> {
+        let bad = || format!("bad {} amount {:?}", self.code, text);
+        let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+        if whole.is_empty() && fraction.is_empty() {
+            return Err(bad());
+        }
+        let digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+        if !digits(whole) || !digits(fraction) || fraction.len() > self.minor_units as usize {
+            return Err(bad());
+        }
+        let whole: u32 = if whole.is_empty() {
+            0
+        } else {
+            whole.parse().map_err(|_| bad())?
+        };
+        let fraction: u32 = format!("{:0<width$}", fraction, width = self.minor_units as usize)
+            .parse()
+            .unwrap_or(0);
+        whole
+            .checked_mul(self.scale())
+            .and_then(|amount| amount.checked_add(fraction))
+            .ok_or_else(bad)
+    }
+
+    pub fn format_amount(&self, amount: u32) -> String {
+        if self.minor_units == 0 {
+            return amount.to_string();
+        }
+        format!(
+            "{}.{:0width$}",
+            amount / self.scale(),
+            amount % self.scale(),
+            width = self.minor_units as usize
+        )
+    }
+
+    /// `amount` rounded to the nearest amount that can be paid in cash.
+    pub fn round_for_cash(&self, amount: u32) -> u32 {
+        match self.cash_rounding {
+            Some(step) if step > 1 => {
+                let down = amount - amount % step;
+                if amount % step * 2 >= step {
+                    down.saturating_add(step)
+                } else {
+                    down
+                }
+            }
+            _ => amount,
+        }
+    }
+
+    fn values(&self) -> Vec<u32> {
+        self.denominations.iter().map(|d| d.value).collect()
+    }
+
+    /// Whether greedy change is optimal for every amount, or `None` if the
+    /// denominations are too large to check.
+    pub fn is_canonical(&self) -> Option<bool> {
+        self.canonical
+    }
+
+    pub fn denomination(&self, name: &str) -> Option<&Denomination> {
+        self.denominations.iter().find(|d| d.name == name)
+    }
+
+    /// Change for `amount`, largest denomination first.
+    pub fn change(&self, amount: u32, strategy: Strategy) -> Result<Vec<Piece<'_>>, ChangeError> {
+        let canonical = self.canonical == Some(true);
+        let coins = coin_change_with(amount, &self.values(), strategy, canonical)?;
+        Ok(self.pieces(&coins))
+    }
+
+    /// Groups runs of equal values, as `coin_change` returns them, into named
+    /// pieces. Every value must be one of the currency's denominations.
+    pub fn pieces(&self, values: &[u32]) -> Vec<Piece<'_>> {
+        let by_value: HashMap<u32, &Denomination> =
+            self.denominations.iter().map(|d| (d.value, d)).collect();
+        let mut pieces: Vec<Piece> = Vec::new();
+        for value in values {
+            match pieces.last_mut() {
+                Some(piece) if piece.denomination.value == *value => piece.count += 1,
+                _ => pieces.push(Piece {
+                    denomination: by_value[value],
+                    count: 1,
+                }),
+            }
+        }
+        pieces
+    }
+}
+
+#[derive(Debug)]
+pub enum RegistryError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Invalid { code: String, message: String },
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::Io(e) => write!(f, "reading currencies: {}", e),
+            RegistryError::Parse(e) => write!(f, "parsing currencies: {}", e),
+            RegistryError::Invalid { code, message } => write!(f, "currency {}: {}", code, message),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    currencies: BTreeMap<String, Currency>,
+}
+
+impl Registry {
+    pub fn from_toml(text: &str) -> Result<Registry, RegistryError> {
+        let mut currencies: BTreeMap<String, Currency> =
+            toml::from_str(text).map_err(RegistryError::Parse)?;
+        for (code, currency) in &mut currencies {
+            let invalid = |message: &str| RegistryError::Invalid {
+                code: code.clone(),
+                message: message.to_string(),
+            };
+            if currency.denominations.is_empty() {
+                return Err(invalid("no denominations"));
+            }
+            if currency.minor_units > 6 {
+                return Err(invalid("more than 6 minor units"));
+            }
+            if currency.denominations.iter().any(|d| d.value == 0) {
+                return Err(invalid("a denomination is worth nothing"));
+            }
+            currency.denominations.sort_by_key(|d| d.value);
+            if currency
+                .denominations
+                .windows(2)
+                .any(|pair| pair[0].value == pair[1].value)
+            {
+                return Err(invalid("two denominations have the same value"));
+            }
+            currency.code = code.to_uppercase();
+            currency.canonical = is_canonical(&currency.values()).ok();
+        }
+        // codes are matched without regard to case
+        let currencies = currencies
+            .into_values()
+            .map(|currency| (currency.code.clone(), currency))
+            .collect();
+        Ok(Registry { currencies })
+    }
+
+    pub fn load(path: &Path) -> Result<Registry, RegistryError> {
+        let text = fs::read_to_string(path).map_err(RegistryError::Io)?;
+        Registry::from_toml(&text)
+    }
+
+    pub fn get(&self, code: &str) -> Option<&Currency> {
+        self.currencies.get(&code.to_uppercase())
+    }
+
+    pub fn codes(&self) -> impl Iterator<Item = &str> {
+        self.currencies.keys().map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> Registry {
+        Registry::from_toml(DEFAULT_CONFIG).unwrap()
+    }
+
+    #[test]
+    fn test_default_registry_has_the_major_currencies() {
+        let registry = registry();
+        assert_eq!(
+            registry.codes().collect::<Vec<_>>(),
+            ["CAD", "EUR", "GBP", "JPY", "USD"]
+        );
+        assert!(registry.get("usd").is_some());
+        for code in registry.codes() {
+            assert_eq!(registry.get(code).unwrap().is_canonical(), Some(true));
+        }
+    }
+
+    #[test]
+    fn test_amounts_parse_and_format_in_minor_units() {
+        let registry = registry();
+        let (usd, jpy) = (registry.get("USD").unwrap(), registry.get("JPY").unwrap());
+        assert_eq!(usd.parse_amount("12.34"), Ok(1234));
+        assert_eq!(usd.parse_amount("12.3"), Ok(1230));
+        assert_eq!(usd.parse_amount("12"), Ok(1200));
+        assert_eq!(usd.parse_amount(".05"), Ok(5));
+        assert!(usd.parse_amount("12.345").is_err());
+        assert!(usd.parse_amount("-1").is_err());
+        assert!(usd.parse_amount("99999999").is_err());
+        assert_eq!(jpy.parse_amount("1500"), Ok(1500));
+        assert!(jpy.parse_amount("1500.5").is_err());
+
+        assert_eq!(usd.format_amount(1205), "12.05");
+        assert_eq!(jpy.format_amount(1500), "1500");
+    }
+
+    #[test]
+    fn test_change_is_named_and_grouped() {
+        let registry = registry();
+        let usd = registry.get("USD").unwrap();
+        let change: Vec<(&str, u32)> = usd
+            .change(3741, Strategy::Greedy)
+            .unwrap()
+            .iter()
+            .map(|p| (p.denomination.name.as_str(), p.count))
+            .collect();
+        assert_eq!(
+            change,
+            [
+                ("$20 bill", 1),
+                ("$10 bill", 1),
+                ("$5 bill", 1),
+                ("$1 bill", 2),
+                ("quarter", 1),
+                ("dime", 1),
+                ("nickel", 1),
+                ("penny", 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cash_rounding_to_the_nearest_nickel() {
+        let registry = registry();
+        let cad = registry.get("CAD").unwrap();
+        assert_eq!(cad.round_for_cash(1232), 1230);
+        assert_eq!(cad.round_for_cash(1233), 1235);
+        assert_eq!(cad.round_for_cash(1237), 1235);
+        assert_eq!(cad.round_for_cash(1238), 1240);
+        assert_eq!(registry.get("USD").unwrap().round_for_cash(1233), 1233);
+    }
+
+    #[test]
+    fn test_canonical_is_checked_at_load() {
+        let config = r#"
+            [XTS]
+            name = "test"
+            minor_units = 0
+            denominations = [
+                { name = "one", value = 1, kind = "coin" },
+                { name = "three", value = 3, kind = "coin" },
+                { name = "four", value = 4, kind = "coin" },
+            ]
+        "#;
+        let registry = Registry::from_toml(config).unwrap();
+        let xts = registry.get("XTS").unwrap();
+        assert_eq!(xts.is_canonical(), Some(false));
+        let change: Vec<(&str, u32)> = xts
+            .change(6, Strategy::Optimal)
+            .unwrap()
+            .iter()
+            .map(|p| (p.denomination.name.as_str(), p.count))
+            .collect();
+        assert_eq!(change, [("three", 2)]);
+    }
+
+    #[test]
+    fn test_invalid_configs_are_rejected() {
+        let duplicate = r#"
+            [XTS]
+            name = "test"
+            minor_units = 2
+            denominations = [
+                { name = "a", value = 5, kind = "coin" },
+                { name = "b", value = 5, kind = "bill" },
+            ]
+        "#;
+        assert!(matches!(
+            Registry::from_toml(duplicate),
+            Err(RegistryError::Invalid { .. })
+        ));
+        assert!(matches!(
+            Registry::from_toml("[XTS]\nname = 1"),
+            Err(RegistryError::Parse(_))
+        ));
+    }
+}
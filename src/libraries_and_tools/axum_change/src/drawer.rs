@@ -0,0 +1,126 @@
+// A cash drawer: a currency and how many of each of its denominations it has.
+//
+// Change comes out of the drawer's own stock. When exact change can't be made
+// from what is left, the drawer is left as it was.
+use crate::currency::{Currency, Piece};
+use crate::{ChangeError, Strategy, limited_coin_change};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Drawer {
+    currency: Currency,
+    // denomination value -> count
+    contents: BTreeMap<u32, u32>,
+}
+
+impl Drawer {
+    /// A drawer holding `contents`, counts keyed by denomination name.
+    /// Denominations left out start empty.
+    pub fn new(currency: Currency, contents: &HashMap<String, u32>) -> Result<Drawer, String> {
+        let mut values = BTreeMap::new();
+        for (name, &count) in contents {
+            let Some(denomination) = currency.denomination(name) else {
+                return Err(format!("{} has no denomination {:?}", currency.code, name));
+            };
+            values.insert(denomination.value, count);
+        }
+        Ok(Drawer {
+            currency,
+            contents: values,
+        })
+    }
+
+    pub fn currency(&self) -> &Currency {
+        &self.currency
+    }
+
+    /// What the drawer holds, in minor units.
+    pub fn total(&self) -> u64 {
+        self.contents
+            .iter()
+            .map(|(&value, &count)| value as u64 * count as u64)
+            .sum()
+    }
+
+    /// What the drawer holds, largest denomination first.
+    pub fn pieces(&self) -> Vec<Piece<'_>> {
+        self.currency
+            .denominations
+            .iter()
+            .rev()
+            .filter_map(|denomination| {
+                let count = self.contents.get(&denomination.value).copied()?;
+                (count > 0).then_some(Piece {
+                    denomination,
+                    count,
+                })
+            })
+            .collect()
+    }
+
+    /// Takes change for `amount` out of the drawer, largest first.
+    pub fn make_change(
+        &mut self,
+        amount: u32,
+        strategy: Strategy,
+    ) -> Result<Vec<u32>, ChangeError> {
+        let inventory: Vec<(u32, u32)> = self.contents.iter().map(|(&v, &c)| (v, c)).collect();
+        let change = limited_coin_change(amount, &inventory, strategy)?;
+        for value in &change {
+            if let Some(count) = self.contents.get_mut(value) {
+                *count -= 1;
+            }
+        }
+        Ok(change)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::currency::{DEFAULT_CONFIG, Registry};
+
+    fn drawer(contents: &[(&str, u32)]) -> Result<Drawer, String> {
+        let registry = Registry::from_toml(DEFAULT_CONFIG).unwrap();
+        let contents = contents
+            .iter()
+            .map(|&(name, count)| (name.to_string(), count))
+            .collect();
+        Drawer::new(registry.get("USD").unwrap().clone(), &contents)
+    }
+
+    fn counts(drawer: &Drawer) -> Vec<(&str, u32)> {
+        drawer
+            .pieces()
+            .iter()
+            .map(|p| (p.denomination.name.as_str(), p.count))
+            .collect()
+    }
+
+    #[test]
+    fn test_change_is_debited_from_the_drawer() {
+        let mut drawer = drawer(&[("quarter", 1), ("dime", 3), ("penny", 2)]).unwrap();
+        assert_eq!(drawer.total(), 57);
+
+        let change = drawer.make_change(30, Strategy::Optimal).unwrap();
+        assert_eq!(change, [10, 10, 10]);
+        assert_eq!(counts(&drawer), [("quarter", 1), ("penny", 2)]);
+
+        // more than is left, which leaves the drawer as it was
+        assert_eq!(
+            drawer.make_change(28, Strategy::Optimal),
+            Err(ChangeError::Impossible { amount: 28 })
+        );
+        assert_eq!(drawer.total(), 27);
+        assert_eq!(drawer.make_change(27, Strategy::Greedy), Ok(vec![25, 1, 1]));
+        assert_eq!(drawer.total(), 0);
+    }
+
+    #[test]
+    fn test_unknown_denominations_are_rejected() {
+        assert_eq!(
+            drawer(&[("doubloon", 3)]),
+            Err("USD has no denomination \"doubloon\"".to_string())
+        );
+    }
+}
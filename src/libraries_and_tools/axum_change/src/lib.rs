@@ -1,18 +1,280 @@
+pub mod currency;
+pub mod drawer;
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub const US_COINS: [u32; 4] = [1, 5, 10, 25];
+
+// optimal change takes a table entry per unit of the amount
+pub const MAX_OPTIMAL_AMOUNT: u32 = 1_000_000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Strategy {
+    /// Largest coin first. Fast, and optimal for canonical systems like US coins.
+    #[default]
+    Greedy,
+    /// Fewest coins, found by dynamic programming.
+    Optimal,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChangeError {
+    NoDenominations,
+    ZeroDenomination,
+    /// The coins can't make the amount, or greedy's pick of them can't.
+    Impossible {
+        amount: u32,
+    },
+    TooLarge {
+        amount: u32,
+    },
+}
+
+impl fmt::Display for ChangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChangeError::NoDenominations => write!(f, "no denominations given"),
+            ChangeError::ZeroDenomination => write!(f, "denominations must be above zero"),
+            ChangeError::Impossible { amount } => {
+                write!(f, "{} cannot be made from these denominations", amount)
+            }
+            ChangeError::TooLarge { amount } => write!(
+                f,
+                "{} is too large to search exhaustively (max {})",
+                amount, MAX_OPTIMAL_AMOUNT
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ChangeError {}
+
 pub fn greedy_coin_change(amount: u32) -> Vec<u32> {
-    let mut coins = vec![1, 5, 10, 25];
-    coins.sort();
-    coins.reverse();
+    // US coins include a 1, so every amount can be made
+    coin_change(amount, &US_COINS, Strategy::Greedy).unwrap_or_default()
+}
+
+/// Coins adding up to `amount`, largest first.
+///
+/// Optimal change checks the coins with `is_canonical` first. Callers making
+/// change from one coin system over and over should check it once and call
+/// `coin_change_with` instead.
+pub fn coin_change(
+    amount: u32,
+    denominations: &[u32],
+    strategy: Strategy,
+) -> Result<Vec<u32>, ChangeError> {
+    let canonical = strategy == Strategy::Optimal && is_canonical(denominations) == Ok(true);
+    coin_change_with(amount, denominations, strategy, canonical)
+}
 
+/// `coin_change` for coins already checked: `canonical` says whether greedy
+/// change is optimal for them, as `is_canonical` found.
+pub fn coin_change_with(
+    amount: u32,
+    denominations: &[u32],
+    strategy: Strategy,
+    canonical: bool,
+) -> Result<Vec<u32>, ChangeError> {
+    let coins = sorted(denominations)?;
+    match strategy {
+        Strategy::Greedy => greedy(amount, &coins),
+        // greedy is already optimal for a canonical system, at any size
+        Strategy::Optimal if canonical => greedy(amount, &coins),
+        Strategy::Optimal if amount > MAX_OPTIMAL_AMOUNT => Err(ChangeError::TooLarge { amount }),
+        Strategy::Optimal => optimal(amount, &coins),
+    }
+}
+
+/// Whether greedy change uses the fewest coins for every amount.
+///
+/// By Kozen and Zaks (1994) the smallest amount where greedy does worse, if
+/// there is one, is below the sum of the two largest coins, so only amounts up
+/// to there are checked. Systems whose two largest coins add up to more than
+/// `MAX_OPTIMAL_AMOUNT` are too large to check.
+pub fn is_canonical(denominations: &[u32]) -> Result<bool, ChangeError> {
+    let coins = sorted(denominations)?;
+    let limit = coins
+        .iter()
+        .take(2)
+        .fold(0u32, |sum, &coin| sum.saturating_add(coin));
+    let table = fewest_coins(limit, &coins)?;
+    Ok((1..limit).all(|amount| match greedy(amount, &coins) {
+        Ok(change) => Some(change.len() as u32) == table[amount as usize].map(|(n, _)| n),
+        Err(_) => table[amount as usize].is_none(),
+    }))
+}
+
+// distinct denominations, largest first
+fn sorted(denominations: &[u32]) -> Result<Vec<u32>, ChangeError> {
+    if denominations.is_empty() {
+        return Err(ChangeError::NoDenominations);
+    }
+    if denominations.contains(&0) {
+        return Err(ChangeError::ZeroDenomination);
+    }
+    let mut coins = denominations.to_vec();
+    coins.sort_unstable_by(|a, b| b.cmp(a));
+    coins.dedup();
+    Ok(coins)
+}
+
+fn greedy(amount: u32, coins: &[u32]) -> Result<Vec<u32>, ChangeError> {
     let mut change = vec![];
     let mut remaining = amount;
 
-    for coin in coins {
+    for &coin in coins {
         while remaining >= coin {
             remaining -= coin;
             change.push(coin);
         }
     }
-    change
+    if remaining > 0 {
+        return Err(ChangeError::Impossible { amount });
+    }
+    Ok(change)
+}
+
+fn optimal(amount: u32, coins: &[u32]) -> Result<Vec<u32>, ChangeError> {
+    let table = fewest_coins(amount, coins)?;
+    let mut change = vec![];
+    let mut remaining = amount as usize;
+    while remaining > 0 {
+        let Some((_, coin)) = table[remaining] else {
+            return Err(ChangeError::Impossible { amount });
+        };
+        change.push(coin);
+        remaining -= coin as usize;
+    }
+    change.sort_unstable_by(|a, b| b.cmp(a));
+    Ok(change)
+}
+
+// for every amount up to `amount`, the fewest coins making it and the last
+// coin used, or `None` where it can't be made
+fn fewest_coins(amount: u32, coins: &[u32]) -> Result<Vec<Option<(u32, u32)>>, ChangeError> {
+    if amount > MAX_OPTIMAL_AMOUNT {
+        return Err(ChangeError::TooLarge { amount });
+    }
+    let mut table: Vec<Option<(u32, u32)>> = vec![None; amount as usize + 1];
+    table[0] = Some((0, 0));
+    for total in 1..=amount as usize {
+        table[total] = coins
+            .iter()
+            .filter(|&&coin| coin as usize <= total)
+            .filter_map(|&coin| table[total - coin as usize].map(|(n, _)| (n + 1, coin)))
+            .min_by_key(|&(n, _)| n);
+    }
+    Ok(table)
+}
+
+/// Coins adding up to `amount`, largest first, using no more of each coin than
+/// `inventory` holds. `inventory` pairs each coin with how many there are.
+///
+/// Running short of a coin can leave greedy stuck even for a canonical system:
+/// 30 cents from one quarter and three dimes. Greedy then falls back on optimal
+/// change, so either strategy only fails when no combination works; optimal
+/// change also always finds the fewest coins.
+pub fn limited_coin_change(
+    amount: u32,
+    inventory: &[(u32, u32)],
+    strategy: Strategy,
+) -> Result<Vec<u32>, ChangeError> {
+    if inventory.iter().any(|&(coin, _)| coin == 0) {
+        return Err(ChangeError::ZeroDenomination);
+    }
+    let mut stock: Vec<(u32, u32)> = Vec::new();
+    for &(coin, count) in inventory {
+        match stock.iter_mut().find(|(c, _)| *c == coin) {
+            Some((_, total)) => *total = total.saturating_add(count),
+            None => stock.push((coin, count)),
+        }
+    }
+    stock.sort_unstable_by_key(|&(coin, _)| std::cmp::Reverse(coin));
+    let held: u64 = stock
+        .iter()
+        .map(|&(coin, count)| coin as u64 * count as u64)
+        .sum();
+    if amount as u64 > held {
+        return Err(ChangeError::Impossible { amount });
+    }
+    match strategy {
+        Strategy::Greedy => {
+            limited_greedy(amount, &stock).or_else(|_| limited_optimal(amount, &stock))
+        }
+        Strategy::Optimal => limited_optimal(amount, &stock),
+    }
+}
+
+fn limited_greedy(amount: u32, stock: &[(u32, u32)]) -> Result<Vec<u32>, ChangeError> {
+    let mut change = vec![];
+    let mut remaining = amount;
+    for &(coin, count) in stock {
+        let take = count.min(remaining / coin);
+        remaining -= take * coin;
+        change.extend(std::iter::repeat_n(coin, take as usize));
+    }
+    if remaining > 0 {
+        return Err(ChangeError::Impossible { amount });
+    }
+    Ok(change)
+}
+
+// Bounded knapsack: each coin's stock is split into lots of 1, 2, 4, ... coins
+// so that any count up to the stock is some set of lots, and each lot is then
+// taken or not.
+fn limited_optimal(amount: u32, stock: &[(u32, u32)]) -> Result<Vec<u32>, ChangeError> {
+    if amount > MAX_OPTIMAL_AMOUNT {
+        return Err(ChangeError::TooLarge { amount });
+    }
+    let amount_len = amount as usize + 1;
+    let mut lots: Vec<(u32, u32)> = Vec::new();
+    for &(coin, mut count) in stock {
+        let mut size = 1;
+        while count > 0 {
+            let lot = size.min(count);
+            if coin as u64 * lot as u64 <= amount as u64 {
+                lots.push((coin, lot));
+            }
+            count -= lot;
+            size = size.saturating_mul(2);
+        }
+    }
+
+    let mut fewest = vec![u32::MAX; amount_len];
+    fewest[0] = 0;
+    // taken[i] has bit `a` set if lot `i` is used in the best way to make `a`
+    // from lots `0..=i`
+    let words = amount_len.div_ceil(64);
+    let mut taken: Vec<Vec<u64>> = Vec::with_capacity(lots.len());
+    for &(coin, lot) in &lots {
+        let mut bits = vec![0u64; words];
+        let value = (coin * lot) as usize;
+        for a in (value..amount_len).rev() {
+            let with = fewest[a - value].saturating_add(lot);
+            if with < fewest[a] {
+                fewest[a] = with;
+                bits[a / 64] |= 1 << (a % 64);
+            }
+        }
+        taken.push(bits);
+    }
+    if fewest[amount as usize] == u32::MAX {
+        return Err(ChangeError::Impossible { amount });
+    }
+
+    let mut change = vec![];
+    let mut remaining = amount as usize;
+    for (i, &(coin, lot)) in lots.iter().enumerate().rev() {
+        if taken[i][remaining / 64] & (1 << (remaining % 64)) != 0 {
+            change.extend(std::iter::repeat_n(coin, lot as usize));
+            remaining -= (coin * lot) as usize;
+        }
+    }
+    change.sort_unstable_by(|a, b| b.cmp(a));
+    Ok(change)
 }
 
 #[cfg(test)]
@@ -28,4 +290,125 @@ mod tests {
         assert_eq!(greedy_coin_change(29), vec![25, 1, 1, 1, 1]);
         assert_eq!(greedy_coin_change(37), vec![25, 10, 1, 1]);
     }
+
+    #[test]
+    fn test_optimal_beats_greedy_on_non_canonical_coins() {
+        let coins = [1, 3, 4];
+        assert_eq!(coin_change(6, &coins, Strategy::Greedy), Ok(vec![4, 1, 1]));
+        assert_eq!(coin_change(6, &coins, Strategy::Optimal), Ok(vec![3, 3]));
+        assert_eq!(coin_change(0, &coins, Strategy::Optimal), Ok(vec![]));
+        // order and repeats in the input don't matter
+        assert_eq!(
+            coin_change(37, &[10, 1, 25, 5, 1], Strategy::Optimal),
+            Ok(vec![25, 10, 1, 1])
+        );
+    }
+
+    #[test]
+    fn test_canonical_systems() {
+        assert_eq!(is_canonical(&US_COINS), Ok(true));
+        assert_eq!(is_canonical(&[1, 2, 5, 10, 20, 50, 100, 200]), Ok(true));
+        assert_eq!(is_canonical(&[1, 3, 4]), Ok(false));
+        // the old British 1, 3, 6, 12, 24, 30: greedy pays 48 as 30 + 12 + 6
+        assert_eq!(is_canonical(&[1, 3, 6, 12, 24, 30]), Ok(false));
+        assert_eq!(
+            is_canonical(&[1, u32::MAX]),
+            Err(ChangeError::TooLarge { amount: u32::MAX })
+        );
+    }
+
+    #[test]
+    fn test_impossible_and_invalid_systems() {
+        // greedy takes the 5 and is stuck; 2 + 2 + 2 works
+        assert_eq!(
+            coin_change(6, &[2, 5], Strategy::Greedy),
+            Err(ChangeError::Impossible { amount: 6 })
+        );
+        assert_eq!(
+            coin_change(6, &[2, 5], Strategy::Optimal),
+            Ok(vec![2, 2, 2])
+        );
+        assert_eq!(
+            coin_change(3, &[2, 5], Strategy::Optimal),
+            Err(ChangeError::Impossible { amount: 3 })
+        );
+        assert_eq!(
+            coin_change(1, &[], Strategy::Greedy),
+            Err(ChangeError::NoDenominations)
+        );
+        assert_eq!(
+            coin_change(1, &[0, 1], Strategy::Greedy),
+            Err(ChangeError::ZeroDenomination)
+        );
+        assert_eq!(
+            coin_change(u32::MAX, &[1, 3, 4], Strategy::Optimal),
+            Err(ChangeError::TooLarge { amount: u32::MAX })
+        );
+        // no table is needed for a canonical system
+        assert_eq!(
+            coin_change(2_000_000, &US_COINS, Strategy::Optimal).map(|c| c.len()),
+            Ok(80_000)
+        );
+    }
+
+    #[test]
+    fn test_limited_inventory() {
+        let drawer = [(25, 1), (10, 3), (1, 10)];
+        // greedy spends the quarter and runs out of small coins
+        assert_eq!(
+            limited_coin_change(30, &drawer, Strategy::Greedy),
+            Ok(vec![25, 1, 1, 1, 1, 1])
+        );
+        assert_eq!(
+            limited_coin_change(30, &drawer, Strategy::Optimal),
+            Ok(vec![10, 10, 10])
+        );
+        let no_pennies = [(25, 1), (10, 3)];
+        // greedy spends the quarter and is stuck, so the dimes are used instead
+        assert_eq!(
+            limited_coin_change(30, &no_pennies, Strategy::Greedy),
+            Ok(vec![10, 10, 10])
+        );
+        assert_eq!(
+            limited_coin_change(15, &no_pennies, Strategy::Greedy),
+            Err(ChangeError::Impossible { amount: 15 })
+        );
+        assert_eq!(
+            limited_coin_change(30, &no_pennies, Strategy::Optimal),
+            Ok(vec![10, 10, 10])
+        );
+        // more than the drawer holds, and an amount it can't make exactly
+        assert_eq!(
+            limited_coin_change(56, &no_pennies, Strategy::Optimal),
+            Err(ChangeError::Impossible { amount: 56 })
+        );
+        assert_eq!(
+            limited_coin_change(15, &no_pennies, Strategy::Optimal),
+            Err(ChangeError::Impossible { amount: 15 })
+        );
+        assert_eq!(limited_coin_change(0, &[], Strategy::Optimal), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_limited_optimal_respects_every_count() {
+        // lots of 1, 2, 4 and 3 coins cover any count up to 10
+        let inventory = [(7, 10), (5, 3), (1, 2)];
+        for amount in 0..=87 {
+            let Ok(change) = limited_coin_change(amount, &inventory, Strategy::Optimal) else {
+                continue;
+            };
+            assert_eq!(change.iter().sum::<u32>(), amount);
+            for &(coin, count) in &inventory {
+                assert!(change.iter().filter(|&&c| c == coin).count() <= count as usize);
+            }
+        }
+        assert_eq!(
+            limited_coin_change(70, &inventory, Strategy::Optimal),
+            Ok(vec![7; 10])
+        );
+        assert_eq!(
+            limited_coin_change(86, &inventory, Strategy::Optimal),
+            Ok([vec![7; 10], vec![5; 3], vec![1]].concat())
+        );
+    }
 }
@@ -1,33 +1,521 @@
-use axum::extract::Path;
-use axum::{Json, Router, routing::get};
-use axum_change::greedy_coin_change;
-use serde_json::json;
+use axum::extract::{FromRef, Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{
+    Json, Router,
+    routing::{get, post},
+};
+use axum_change::currency::{DEFAULT_CONFIG, Registry};
+use axum_change::drawer::Drawer;
+use axum_change::{ChangeError, Strategy};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
 async fn root() -> &'static str {
     "
-    Greedy Coin Change
+    Coin Change
 
     ** Primary Route: **
-    /change/dollars/cents
+    /change/currency/amount      e.g. /change/EUR/12.34
+
+    ** Query Parameters: **
+    strategy=greedy|optimal      (default greedy)
+
+    ** Other Routes: **
+    /currencies
+    /change/dollars/cents        US dollars, as before
+
+    ** Cash Drawers: **
+    POST /drawer                 {\"currency\": \"USD\", \"contents\": {\"quarter\": 40}}
+    GET  /drawer/id
+    POST /drawer/id/change       {\"amount\": \"1.25\", \"strategy\": \"optimal\"}
     "
 }
 
-async fn change(Path((dollars, cents)): Path<(u32, u32)>) -> impl axum::response::IntoResponse {
-    let amount = dollars * 100 + cents;
-    let change = greedy_coin_change(amount);
+// drawers live only as long as the process
+#[derive(Debug, Default)]
+struct Drawers {
+    next_id: u64,
+    drawers: HashMap<u64, Drawer>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Replace {
+    Done,
+    // the drawer no longer holds what the change was worked out from
+    Changed,
+    Gone,
+}
+
+impl Drawers {
+    // puts `after` in place of drawer `id` if it still holds `before`
+    fn replace(&mut self, id: u64, before: &Drawer, after: Drawer) -> Replace {
+        match self.drawers.get_mut(&id) {
+            Some(drawer) if drawer == before => {
+                *drawer = after;
+                Replace::Done
+            }
+            Some(_) => Replace::Changed,
+            None => Replace::Gone,
+        }
+    }
+}
+
+// times change is worked out for a drawer other requests keep changing
+const CHANGE_ATTEMPTS: usize = 3;
+
+#[derive(Clone)]
+struct AppState {
+    registry: Arc<Registry>,
+    drawers: Arc<Mutex<Drawers>>,
+}
+
+impl FromRef<AppState> for Arc<Registry> {
+    fn from_ref(state: &AppState) -> Self {
+        state.registry.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Mutex<Drawers>> {
+    fn from_ref(state: &AppState) -> Self {
+        state.drawers.clone()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ChangeQuery {
+    #[serde(default)]
+    strategy: Strategy,
+}
+
+fn error(status: StatusCode, message: impl std::fmt::Display) -> Response {
+    let body = Json(json!({ "error": message.to_string() }));
+    (status, body).into_response()
+}
+
+async fn currencies(State(registry): State<Arc<Registry>>) -> Response {
+    let currencies: Vec<_> = registry
+        .codes()
+        .filter_map(|code| registry.get(code))
+        .map(|currency| {
+            json!({
+                "code": currency.code,
+                "name": currency.name,
+                "denominations": currency.denominations,
+            })
+        })
+        .collect();
+    Json(currencies).into_response()
+}
+
+async fn change(
+    State(registry): State<Arc<Registry>>,
+    Path((currency, amount)): Path<(String, String)>,
+    Query(query): Query<ChangeQuery>,
+) -> Response {
+    // the old /change/:dollars/:cents form
+    let (currency, amount) = match (currency.parse::<u32>(), amount.parse::<u32>()) {
+        (Ok(dollars), Ok(cents)) => {
+            let Some(total) = dollars.checked_mul(100).and_then(|a| a.checked_add(cents)) else {
+                return error(StatusCode::BAD_REQUEST, "amount too large");
+            };
+            (
+                "USD".to_string(),
+                format!("{}.{:02}", total / 100, total % 100),
+            )
+        }
+        _ => (currency, amount),
+    };
+    let Some(currency) = registry.get(&currency) else {
+        return error(
+            StatusCode::NOT_FOUND,
+            format!("unknown currency {}", currency),
+        );
+    };
+    let amount = match currency.parse_amount(&amount) {
+        Ok(amount) => amount,
+        Err(message) => return error(StatusCode::BAD_REQUEST, message),
+    };
+    let paid = currency.round_for_cash(amount);
+    let change = match currency.change(paid, query.strategy) {
+        Ok(change) => change,
+        Err(e) => return error(StatusCode::BAD_REQUEST, e),
+    };
     let json = json!({
-        "dollars": dollars,
-        "cents": cents,
+        "currency": currency.code,
+        "amount": currency.format_amount(amount),
+        // differs from the amount when it is rounded for cash
+        "paid": currency.format_amount(paid),
+        "strategy": query.strategy,
+        // null when the system is too large to check
+        "canonical": currency.is_canonical(),
         "change": change
     });
-    Json(json)
+    Json(json).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct NewDrawer {
+    currency: String,
+    // count of each denomination, by name
+    contents: HashMap<String, u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DrawerChange {
+    amount: String,
+    #[serde(default)]
+    strategy: Strategy,
+}
+
+fn drawer_json(id: u64, drawer: &Drawer) -> Value {
+    let currency = drawer.currency();
+    // a drawer's total can pass what an amount can hold
+    let total = u32::try_from(drawer.total())
+        .map(|total| currency.format_amount(total))
+        .unwrap_or_else(|_| format!("{} minor units", drawer.total()));
+    json!({
+        "id": id,
+        "currency": currency.code,
+        "total": total,
+        "contents": drawer.pieces(),
+    })
+}
+
+async fn create_drawer(
+    State(registry): State<Arc<Registry>>,
+    State(drawers): State<Arc<Mutex<Drawers>>>,
+    Json(request): Json<NewDrawer>,
+) -> Response {
+    let Some(currency) = registry.get(&request.currency) else {
+        return error(
+            StatusCode::NOT_FOUND,
+            format!("unknown currency {}", request.currency),
+        );
+    };
+    let drawer = match Drawer::new(currency.clone(), &request.contents) {
+        Ok(drawer) => drawer,
+        Err(message) => return error(StatusCode::BAD_REQUEST, message),
+    };
+    let mut drawers = drawers.lock().unwrap();
+    drawers.next_id += 1;
+    let id = drawers.next_id;
+    let json = drawer_json(id, &drawer);
+    drawers.drawers.insert(id, drawer);
+    (StatusCode::CREATED, Json(json)).into_response()
+}
+
+async fn show_drawer(State(drawers): State<Arc<Mutex<Drawers>>>, Path(id): Path<u64>) -> Response {
+    let drawers = drawers.lock().unwrap();
+    match drawers.drawers.get(&id) {
+        Some(drawer) => Json(drawer_json(id, drawer)).into_response(),
+        None => error(StatusCode::NOT_FOUND, format!("no drawer {}", id)),
+    }
+}
+
+// Change is worked out on a copy of the drawer, off the async workers and
+// without holding the lock, since an optimal search over a large drawer can
+// take a while. It is only kept if the drawer hasn't changed meanwhile;
+// otherwise it is worked out again from what the drawer holds now, up to
+// `CHANGE_ATTEMPTS` times.
+async fn drawer_change(
+    State(drawers): State<Arc<Mutex<Drawers>>>,
+    Path(id): Path<u64>,
+    Json(request): Json<DrawerChange>,
+) -> Response {
+    for _ in 0..CHANGE_ATTEMPTS {
+        let Some(before) = drawers.lock().unwrap().drawers.get(&id).cloned() else {
+            return error(StatusCode::NOT_FOUND, format!("no drawer {}", id));
+        };
+        let amount = match before.currency().parse_amount(&request.amount) {
+            Ok(amount) => amount,
+            Err(message) => return error(StatusCode::BAD_REQUEST, message),
+        };
+        let paid = before.currency().round_for_cash(amount);
+        let mut after = before.clone();
+        let strategy = request.strategy;
+        let (coins, after) =
+            tokio::task::spawn_blocking(move || (after.make_change(paid, strategy), after))
+                .await
+                .unwrap();
+        let coins = match coins {
+            Ok(coins) => coins,
+            // the drawer is fine, it just can't cover this amount
+            Err(e @ ChangeError::Impossible { .. }) => return error(StatusCode::CONFLICT, e),
+            Err(e) => return error(StatusCode::BAD_REQUEST, e),
+        };
+        let mut drawers = drawers.lock().unwrap();
+        match drawers.replace(id, &before, after) {
+            Replace::Done => {}
+            Replace::Changed => continue,
+            Replace::Gone => return error(StatusCode::NOT_FOUND, format!("no drawer {}", id)),
+        }
+        let drawer = &drawers.drawers[&id];
+        let currency = drawer.currency();
+        let json = json!({
+            "amount": currency.format_amount(amount),
+            "paid": currency.format_amount(paid),
+            "strategy": request.strategy,
+            "change": currency.pieces(&coins),
+            "drawer": drawer_json(id, drawer),
+        });
+        return Json(json).into_response();
+    }
+    error(
+        StatusCode::CONFLICT,
+        format!("drawer {} kept changing, try again", id),
+    )
+}
+
+fn app(registry: Registry) -> Router {
+    let state = AppState {
+        registry: Arc::new(registry),
+        drawers: Arc::new(Mutex::new(Drawers::default())),
+    };
+    Router::new()
+        .route("/", get(root))
+        .route("/currencies", get(currencies))
+        .route("/change/:currency/:amount", get(change))
+        .route("/drawer", post(create_drawer))
+        .route("/drawer/:id", get(show_drawer))
+        .route("/drawer/:id/change", post(drawer_change))
+        .with_state(state)
 }
 
 #[tokio::main]
 async fn main() {
-    let app = Router::new()
-        .route("/", get(root))
-        .route("/change/:dollars/:cents", get(change));
+    // CURRENCY_CONFIG names a currencies file to use instead of the built-in one
+    let registry = match std::env::var_os("CURRENCY_CONFIG") {
+        Some(path) => Registry::load(path.as_ref()),
+        None => Registry::from_toml(DEFAULT_CONFIG),
+    }
+    .unwrap();
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(listener, app(registry)).await.unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{Body, to_bytes};
+    use axum::http::Request;
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    const TEST_CURRENCY: &str = r#"
+        [XTS]
+        name = "testing"
+        minor_units = 0
+        denominations = [
+            { name = "one", value = 1, kind = "coin" },
+            { name = "three", value = 3, kind = "coin" },
+            { name = "four", value = 4, kind = "bill" },
+        ]
+    "#;
+
+    async fn get_json(uri: &str) -> (StatusCode, Value) {
+        let config = format!("{}\n{}", DEFAULT_CONFIG, TEST_CURRENCY);
+        let registry = Registry::from_toml(&config).unwrap();
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let response = app(registry).oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_change_names_each_denomination() {
+        let (status, body) = get_json("/change/eur/2.70").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["currency"], json!("EUR"));
+        assert_eq!(
+            body["change"],
+            json!([
+                { "name": "€2 coin", "value": 200, "kind": "coin", "count": 1 },
+                { "name": "50 cent", "value": 50, "kind": "coin", "count": 1 },
+                { "name": "20 cent", "value": 20, "kind": "coin", "count": 1 },
+            ])
+        );
+
+        // the old route still means US dollars
+        let (_, body) = get_json("/change/1/137").await;
+        assert_eq!(body["currency"], json!("USD"));
+        assert_eq!(body["amount"], json!("2.37"));
+    }
+
+    #[tokio::test]
+    async fn test_strategy_comes_from_the_query() {
+        let (_, body) = get_json("/change/XTS/6?strategy=optimal").await;
+        assert_eq!(
+            body["change"],
+            json!([{ "name": "three", "value": 3, "kind": "coin", "count": 2 }])
+        );
+        assert_eq!(body["canonical"], json!(false));
+
+        let (_, body) = get_json("/change/XTS/6").await;
+        assert_eq!(body["change"][0]["name"], json!("four"));
+    }
+
+    #[tokio::test]
+    async fn test_cash_rounding_and_errors() {
+        let (_, body) = get_json("/change/CAD/1.37").await;
+        assert_eq!(
+            (&body["amount"], &body["paid"]),
+            (&json!("1.37"), &json!("1.35"))
+        );
+
+        let (status, _) = get_json("/change/XXX/1").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, body) = get_json("/change/JPY/1.5").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], json!("bad JPY amount \"1.5\""));
+    }
+
+    async fn send(app: &Router, method: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_drawer_change_debits_inventory() {
+        let app = app(Registry::from_toml(DEFAULT_CONFIG).unwrap());
+        let contents = json!({ "quarter": 1, "dime": 3, "penny": 2 });
+        let (status, body) = send(
+            &app,
+            "POST",
+            "/drawer",
+            json!({ "currency": "usd", "contents": contents }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body["total"], json!("0.57"));
+        let drawer = format!("/drawer/{}", body["id"]);
+        let uri = format!("{}/change", drawer);
+
+        let (status, body) = send(
+            &app,
+            "POST",
+            &uri,
+            json!({ "amount": "0.30", "strategy": "optimal" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body["change"],
+            json!([{ "name": "dime", "value": 10, "kind": "coin", "count": 3 }])
+        );
+        assert_eq!(body["drawer"]["total"], json!("0.27"));
+
+        // not enough left: refused, and nothing taken
+        let (status, _) = send(&app, "POST", &uri, json!({ "amount": "0.28" })).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, body) = send(&app, "GET", &drawer, Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total"], json!("0.27"));
+
+        let (status, _) = send(&app, "POST", "/drawer/999/change", json!({ "amount": "1" })).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, body) = send(
+            &app,
+            "POST",
+            "/drawer",
+            json!({ "currency": "USD", "contents": { "doubloon": 1 } }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], json!("USD has no denomination \"doubloon\""));
+    }
+
+    #[tokio::test]
+    async fn test_greedy_drawer_change_falls_back_when_stuck() {
+        let app = app(Registry::from_toml(DEFAULT_CONFIG).unwrap());
+        let contents = json!({ "quarter": 1, "dime": 3 });
+        let (_, body) = send(
+            &app,
+            "POST",
+            "/drawer",
+            json!({ "currency": "USD", "contents": contents }),
+        )
+        .await;
+        let uri = format!("/drawer/{}/change", body["id"]);
+
+        // no strategy given, so greedy, which takes the quarter and is stuck
+        let (status, body) = send(&app, "POST", &uri, json!({ "amount": "0.30" })).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body["change"],
+            json!([{ "name": "dime", "value": 10, "kind": "coin", "count": 3 }])
+        );
+        assert_eq!(body["drawer"]["total"], json!("0.25"));
+    }
+
+    #[test]
+    fn test_change_is_only_kept_for_an_unchanged_drawer() {
+        let registry = Registry::from_toml(DEFAULT_CONFIG).unwrap();
+        let usd = registry.get("USD").unwrap().clone();
+        let contents = HashMap::from([("dime".to_string(), 3)]);
+        let mut drawers = Drawers::default();
+        drawers
+            .drawers
+            .insert(1, Drawer::new(usd, &contents).unwrap());
+
+        let before = drawers.drawers[&1].clone();
+        let mut after = before.clone();
+        after.make_change(10, Strategy::Greedy).unwrap();
+        // another request takes a dime first
+        let mut other = before.clone();
+        other.make_change(10, Strategy::Greedy).unwrap();
+        assert_eq!(drawers.replace(1, &before, other), Replace::Done);
+
+        assert_eq!(drawers.replace(1, &before, after.clone()), Replace::Changed);
+        assert_eq!(drawers.drawers[&1].total(), 20);
+        assert_eq!(drawers.replace(2, &before, after), Replace::Gone);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_drawer_changes_never_share_a_coin() {
+        let app = app(Registry::from_toml(DEFAULT_CONFIG).unwrap());
+        let (_, body) = send(
+            &app,
+            "POST",
+            "/drawer",
+            json!({ "currency": "USD", "contents": { "penny": 40 } }),
+        )
+        .await;
+        let drawer = format!("/drawer/{}", body["id"]);
+        let uri = format!("{}/change", drawer);
+
+        let mut requests = tokio::task::JoinSet::new();
+        for _ in 0..50 {
+            let (app, uri) = (app.clone(), uri.clone());
+            requests
+                .spawn(async move { send(&app, "POST", &uri, json!({ "amount": "0.01" })).await });
+        }
+        let mut paid = 0;
+        while let Some(response) = requests.join_next().await {
+            let (status, body) = response.unwrap();
+            match status {
+                StatusCode::OK => {
+                    assert_eq!(body["change"][0]["count"], json!(1));
+                    paid += 1;
+                }
+                // out of pennies, or beaten to the drawer too often
+                StatusCode::CONFLICT => {}
+                status => panic!("unexpected {}: {}", status, body),
+            }
+        }
+        assert!(paid <= 40);
+        let (_, body) = send(&app, "GET", &drawer, Value::Null).await;
+        assert_eq!(body["total"], json!(format!("0.{:02}", 40 - paid)));
+    }
 }
@@ -1,5 +1,10 @@
 use csv::Writer;
+use data_eng_rust_sandbox::collections::pipeline::{
+    self, DeadLetter, LineSource, Pipeline, Sink, TransformError,
+};
 use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufReader};
 
 fn main() -> Result<(), Box<dyn Error>> {
     // create tuples for veggies with pricing.
@@ -23,38 +28,81 @@ fn main() -> Result<(), Box<dyn Error>> {
         writer.write_record([veggie, &price.to_string()])?;
     }
     writer.flush().expect("Unable to flush writer");
-    create_discounted_products("veggies.csv", "discounted_veggies.csv", 0.1);
+    create_discounted_products(
+        "veggies.csv",
+        "discounted_veggies.csv",
+        "discounted_veggies.rejects.csv",
+        0.1,
+    )?;
 
     Ok(())
 }
 
-fn create_discounted_products(input_filename: &str, output_filename: &str, discount: f64) {
-    let mut file_reader = csv::ReaderBuilder::new()
-        .has_headers(true)
-        .from_path(input_filename)
-        .expect("Unable to read file");
+// writes each priced product as a row of a CSV file
+struct CsvSink(Writer<File>);
+
+impl Sink<[String; 3]> for CsvSink {
+    fn write(&mut self, record: [String; 3]) -> pipeline::Result<()> {
+        self.0.write_record(&record).map_err(io::Error::from)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> pipeline::Result<()> {
+        self.0.flush()?;
+        Ok(())
+    }
+}
+
+// a product line of the input: its name, its price and that price discounted
+fn discount_row(line: &str, discount: f64) -> Result<[String; 3], TransformError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(line.as_bytes());
+    let record = match reader.records().next() {
+        Some(record) => record.map_err(|e| TransformError::new("unreadable row", e))?,
+        None => return Err(TransformError::new("unreadable row", "empty line")),
+    };
+    if record.len() != 2 {
+        return Err(TransformError::new(
+            "wrong column count",
+            format!("expected 2 columns, found {}", record.len()),
+        ));
+    }
+    let price = record[1]
+        .parse::<f64>()
+        .map_err(|e| TransformError::new("bad price", format!("{:?}: {}", &record[1], e)))?;
+    let discounted_price = price * (1.0 - discount);
+    Ok([
+        record[0].to_string(),
+        price.to_string(),
+        discounted_price.to_string(),
+    ])
+}
+
+// Rows that can't be read or priced are written to `rejects_filename` with
+// their line number and why, and the rest of the file is still discounted.
+fn create_discounted_products(
+    input_filename: &str,
+    output_filename: &str,
+    rejects_filename: &str,
+    discount: f64,
+) -> Result<(), Box<dyn Error>> {
+    let input = BufReader::new(File::open(input_filename)?);
     let mut writer = csv::WriterBuilder::new()
         .has_headers(true)
-        .from_path(output_filename)
-        .expect("Unable to write file");
+        .from_path(output_filename)?;
     let header_data = ["product", "price", "discounted price"];
-    writer
-        .write_record(header_data)
-        .expect("Unable to write header");
-    for result in file_reader.records() {
-        let record = result.expect("Unable to read record");
-        let price = record[1].parse::<f64>().expect("Unable to parse price");
-        let discounted_price = price * (1.0 - discount);
-        let out_record = [
-            record[0].to_string(),
-            price.to_string(),
-            discounted_price.to_string(),
-        ];
-        writer
-            .write_record(&out_record)
-            .expect("Unable to write record");
+    writer.write_record(header_data)?;
+    let mut dead_letter = DeadLetter::create(rejects_filename)?;
+    let mut header = true;
+    Pipeline::new(LineSource::new(input))
+        // the input's own header, replaced by the one above
+        .filter(move |_: &String| !std::mem::take(&mut header))
+        .try_map(|line: String| discount_row(&line, discount))
+        .run_with_dead_letter(&mut CsvSink(writer), &mut dead_letter)?;
+    let report = dead_letter.finish()?;
+    if report.total > 0 {
+        eprint!("{}", report);
     }
-    writer
-        .flush()
-        .expect("Unable to flush writer for discounted products.");
+    Ok(())
 }